@@ -1,11 +1,31 @@
 package com.rafael.camera_streamer
 
+import android.net.wifi.WifiManager
 import android.os.Bundle
 import androidx.activity.enableEdgeToEdge
 
 class MainActivity : TauriActivity() {
+  // Without it Android drops multicast (and often broadcast), so discovery finds no receiver.
+  // Held only while the app is in the foreground, the only time it looks for receivers:
+  // multicast reception keeps the Wi-Fi radio awake
+  private var multicastLock: WifiManager.MulticastLock? = null
+
   override fun onCreate(savedInstanceState: Bundle?) {
     enableEdgeToEdge()
     super.onCreate(savedInstanceState)
+    val wifi = applicationContext.getSystemService(WIFI_SERVICE) as WifiManager
+    multicastLock = wifi.createMulticastLock("camstream-discovery").apply {
+      setReferenceCounted(false)
+    }
+  }
+
+  override fun onResume() {
+    super.onResume()
+    multicastLock?.acquire()
+  }
+
+  override fun onPause() {
+    multicastLock?.let { if (it.isHeld) it.release() }
+    super.onPause()
   }
 }
@@ -0,0 +1,139 @@
+#![warn(clippy::all,
+clippy::pedantic,
+clippy::perf,
+clippy::nursery,
+// clippy::cargo,
+clippy::unwrap_used,
+clippy::expect_used)]
+
+//! Finding receivers on the LAN, so the user picks one from a list.
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use camstream_protocol::beacon::MAX_BEACON_LEN;
+use camstream_protocol::mdns::{self, ServiceDaemon};
+use camstream_protocol::{Beacon, PreSharedKey, ReceiverInfo};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+/// How long to listen for answers when the frontend doesn't say.
+pub const DEFAULT_DISCOVERY_TIME: Duration = Duration::from_secs(3);
+
+/// Browses for `_camstream._tcp` services over mDNS for `wait`.
+pub async fn browse_mdns(wait: Duration) -> Result<Vec<ReceiverInfo>, String> {
+    // The browse blocks for all of `wait`
+    let found = tauri::async_runtime::spawn_blocking(move || {
+        let daemon = ServiceDaemon::new()?;
+        let found = mdns::browse(&daemon, wait);
+        let _ = daemon.shutdown();
+        found
+    })
+    .await
+    .map_err(|err| format!("Receiver discovery stopped: {err}"))?
+    .map_err(|err| format!("Couldn't look for receivers: {err}"))?;
+    info!("Found {} receiver(s) over mDNS", found.len());
+    Ok(found)
+}
+
+/// A receiver heard through its UDP beacon.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BeaconReceiver {
+    #[serde(flatten)]
+    pub receiver: ReceiverInfo,
+    /// Ping round trip, `None` if no pong came back in time
+    pub latency_ms: Option<f64>,
+}
+
+/// Listens for receivers' UDP beacons on `port` for `wait`, pinging each one
+/// heard to measure the latency. With a `psk`, only receivers signing with it
+/// are listed.
+pub async fn listen_for_beacons(
+    wait: Duration,
+    port: u16,
+    psk: Option<&PreSharedKey>,
+) -> Result<Vec<BeaconReceiver>, String> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))
+        .await
+        .map_err(|err| format!("Couldn't listen for beacons on port {port}: {err}"))?;
+    let deadline = Instant::now() + wait;
+    // By the address beacons come from, which pongs come from too
+    let mut found: BTreeMap<SocketAddr, BeaconReceiver> = BTreeMap::new();
+    let mut pings: HashMap<String, (SocketAddr, Instant)> = HashMap::new();
+    let mut buffer = [0u8; MAX_BEACON_LEN];
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        let Ok(received) = timeout(left, socket.recv_from(&mut buffer)).await else {
+            break;
+        };
+        let (len, from) = received.map_err(|err| format!("Error receiving beacons: {err}"))?;
+        let beacon = match Beacon::decode(&buffer[..len], psk) {
+            Ok(beacon) => beacon,
+            Err(err) => {
+                debug!("Ignoring a datagram from {from}: {err}");
+                continue;
+            }
+        };
+        match beacon {
+            Beacon::Announce { mut receiver, .. } => {
+                // The address it was heard from works, whatever else it lists
+                if !receiver.addresses.contains(&from.ip()) {
+                    receiver.addresses.insert(0, from.ip());
+                }
+                let latency_ms = found.get(&from).and_then(|known| known.latency_ms);
+                found.insert(
+                    from,
+                    BeaconReceiver {
+                        receiver,
+                        latency_ms,
+                    },
+                );
+                // Again on every announcement until one is answered, pings get lost too
+                if latency_ms.is_none() {
+                    send_ping(&socket, from, psk, &mut pings).await;
+                }
+            }
+            Beacon::Pong { nonce } => {
+                if let Some((to, sent)) = pings.remove(&nonce) {
+                    if let Some(receiver) = found.get_mut(&to) {
+                        receiver.latency_ms = Some(sent.elapsed().as_secs_f64() * 1000.0);
+                    }
+                }
+            }
+            Beacon::Ping { .. } => {}
+        }
+    }
+    info!("Heard {} receiver(s) over UDP beacons", found.len());
+    Ok(found.into_values().collect())
+}
+
+async fn send_ping(
+    socket: &UdpSocket,
+    to: SocketAddr,
+    psk: Option<&PreSharedKey>,
+    pings: &mut HashMap<String, (SocketAddr, Instant)>,
+) {
+    let ping = match Beacon::ping() {
+        Ok(ping) => ping,
+        Err(err) => {
+            warn!("Couldn't make a ping: {err}");
+            return;
+        }
+    };
+    let Beacon::Ping { nonce } = &ping else {
+        return;
+    };
+    // A receiver with a key ignores pings not signed with it
+    let sent = match ping.encode(psk) {
+        Ok(datagram) => socket.send_to(&datagram, to).await,
+        Err(err) => Err(err),
+    };
+    match sent {
+        Ok(_) => {
+            pings.insert(nonce.clone(), (to, Instant::now()));
+        }
+        Err(err) => warn!("Couldn't ping the receiver at {to}: {err}"),
+    }
+}
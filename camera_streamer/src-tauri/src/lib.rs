@@ -6,58 +6,225 @@ clippy::nursery,
 clippy::unwrap_used,
 clippy::expect_used)]
 
-use std::{net::TcpListener, time::Duration};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
 
-use tauri::{command, AppHandle, Emitter};
+use camstream_protocol::beacon::DEFAULT_BEACON_PORT;
+use camstream_protocol::{Fingerprint, PreSharedKey, ReceiverInfo};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{command, AppHandle, Emitter, Manager, State};
 
-use crate::simple_stream::RustLog;
+use crate::discovery::{BeaconReceiver, DEFAULT_DISCOVERY_TIME};
+use crate::logging::RustLog;
+use crate::pairing::PairingSettings;
+use crate::server::{ServerState, ServerStatus};
+use crate::simple_stream::{BridgeConfig, DEFAULT_LATENCY_BUDGET};
+use crate::upstream::{ConnectPolicy, Upstream};
 
+pub mod discovery;
+pub mod logging;
+pub mod pairing;
+pub mod server;
 pub mod simple_stream;
+pub mod tls;
+pub mod upstream;
+pub mod webm;
+
+/// Only the app's own WebView connects to the bridge, so it stays off the network by default.
+const DEFAULT_LISTEN_HOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+/// Port 0 lets the OS pick a free one; the frontend learns it from `server-running`.
+const DEFAULT_LISTEN_PORT: u16 = 0;
+
+/// Where the bridge is listening: result of `start_server` and payload of `server-running`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ServerAddress {
+    pub address: String,
+    pub port: u16,
+}
+
+impl From<SocketAddr> for ServerAddress {
+    fn from(addr: SocketAddr) -> Self {
+        Self {
+            address: addr.ip().to_string(),
+            port: addr.port(),
+        }
+    }
+}
+
+/// Payload of the `server-error` event, for failures after the server started.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ServerError {
+    pub message: String,
+}
+
+/// What the frontend passes to `start_server`; only `tcpAddr` is required.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StartOptions {
+    pub tcp_addr: String,
+    pub listen_host: Option<String>,
+    pub listen_port: Option<u16>,
+    pub connect_timeout_ms: Option<u64>,
+    pub max_connect_attempts: Option<u32>,
+    pub latency_budget_ms: Option<u64>,
+    pub psk: Option<String>,
+    pub receiver_fingerprint: Option<String>,
+}
 
 #[command]
-async fn start_server(app: AppHandle, tcp_addr: String) -> Result<(), String> {
-    let app_clone = app.clone();
-    std::thread::spawn(move || receiver_end(tcp_addr));
-    tokio::time::sleep(Duration::from_millis(500)).await;
-    if let Err(err) = app_clone.emit("server-running", ()) {
-        println!("\n\n\n\nError on server_start: {err}\n\n\n\n");
+async fn start_server(
+    app: AppHandle,
+    state: State<'_, ServerState>,
+    options: StartOptions,
+) -> Result<ServerAddress, String> {
+    let StartOptions {
+        tcp_addr,
+        listen_host,
+        listen_port,
+        connect_timeout_ms,
+        max_connect_attempts,
+        latency_budget_ms,
+        psk,
+        receiver_fingerprint,
+    } = options;
+    let host = match listen_host.as_deref() {
+        Some(host) => host
+            .parse::<IpAddr>()
+            .map_err(|err| format!("Invalid listen address '{host}': {err}"))?,
+        None => DEFAULT_LISTEN_HOST,
     };
-    if let Err(err) = app_clone.emit(
-        "rust-log",
-        RustLog {
-            log_message: "[BACKEND] started server".to_string(),
+    let listen_addr = SocketAddr::new(host, listen_port.unwrap_or(DEFAULT_LISTEN_PORT));
+
+    let mut policy = ConnectPolicy::default();
+    if let Some(timeout_ms) = connect_timeout_ms {
+        policy.connect_timeout = Duration::from_millis(timeout_ms.max(1));
+    }
+    if let Some(attempts) = max_connect_attempts {
+        policy.max_attempts = attempts.max(1);
+    }
+    let tls = match receiver_fingerprint.as_deref().map(str::trim) {
+        Some(fingerprint) if !fingerprint.is_empty() => {
+            let fingerprint = fingerprint
+                .parse::<Fingerprint>()
+                .map_err(|err| format!("Invalid receiver fingerprint: {err}"))?;
+            Some(
+                tls::pinned_client_config(fingerprint)
+                    .map_err(|err| format!("Couldn't set up TLS: {err}"))?,
+            )
+        }
+        _ => None,
+    };
+    if tls.is_none() {
+        warn!("No receiver fingerprint set: the stream goes to the receiver unencrypted");
+    }
+    let config = BridgeConfig {
+        upstream: Upstream {
+            addr: tcp_addr,
+            policy,
+            tls,
         },
-    ) {
-        println!("\n\n\n\nError on server_start: {err}\n\n\n\n");
+        latency_budget: latency_budget_ms.map_or(DEFAULT_LATENCY_BUDGET, Duration::from_millis),
+        psk: psk
+            .filter(|key| !key.is_empty())
+            .map(PreSharedKey::new)
+            .transpose()
+            .map_err(|err| format!("Invalid pre-shared key: {err}"))?,
+    };
+    if config.psk.is_none() {
+        warn!("No pre-shared key set: the bridge and the receiver won't authenticate anyone");
+    }
+
+    // Returns once the listener is bound, so a busy port fails the command itself
+    let bound_addr = ServerAddress::from(state.start(app.clone(), listen_addr, config)?);
+    if let Err(err) = app.emit("server-running", bound_addr.clone()) {
+        warn!("Error on server_start: {err}");
     };
+    info!(
+        "Started server on {}:{}",
+        bound_addr.address, bound_addr.port
+    );
+    Ok(bound_addr)
+}
+
+/// Stops the bridge and closes every client; does nothing if it isn't running.
+#[command]
+async fn stop_server(state: State<'_, ServerState>) -> Result<(), String> {
+    let Some(server) = state.take() else {
+        return Ok(());
+    };
+    server.shutdown().await;
+    info!("Stopped server");
     Ok(())
 }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    println!("Testing loggin init application");
-    tauri::Builder::default()
-        .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![start_server])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+#[command]
+fn server_status(state: State<'_, ServerState>) -> ServerStatus {
+    state.status()
 }
 
-fn receiver_end(tcp_addr: String) -> Result<(), std::io::Error> {
-    let server = TcpListener::bind("0.0.0.0:8080")?;
-    println!("WebSocket server listening on: 0.0.0.0:8080");
+/// Reads a receiver's pairing code (the text of its QR code) into
+/// [`StartOptions`] fields.
+#[command]
+fn parse_pairing(code: String) -> Result<PairingSettings, String> {
+    pairing::settings(&code)
+}
 
-    for stream in server.incoming() {
-        let stream = stream?;
-        let peer_addr = stream.peer_addr()?;
-        println!("New connection from: {}", peer_addr);
+/// Receivers answering on the LAN within `timeout_ms`, for the user to pick from.
+#[command]
+async fn discover_receivers(timeout_ms: Option<u64>) -> Result<Vec<ReceiverInfo>, String> {
+    discovery::browse_mdns(timeout_ms.map_or(DEFAULT_DISCOVERY_TIME, Duration::from_millis)).await
+}
 
-        let tcp_addr = tcp_addr.clone();
+/// Receivers heard broadcasting UDP beacons on `port` within `timeout_ms`, for
+/// networks that block mDNS. With a `psk`, only those signing with it.
+#[command]
+async fn listen_for_beacons(
+    timeout_ms: Option<u64>,
+    port: Option<u16>,
+    psk: Option<String>,
+) -> Result<Vec<BeaconReceiver>, String> {
+    let psk = psk
+        .filter(|key| !key.is_empty())
+        .map(PreSharedKey::new)
+        .transpose()
+        .map_err(|err| format!("Invalid pre-shared key: {err}"))?;
+    discovery::listen_for_beacons(
+        timeout_ms.map_or(DEFAULT_DISCOVERY_TIME, Duration::from_millis),
+        port.unwrap_or(DEFAULT_BEACON_PORT),
+        psk.as_ref(),
+    )
+    .await
+}
 
-        std::thread::spawn(move || {
-            let _ = simple_stream::handle_client(&tcp_addr.clone(), stream, peer_addr);
-        });
-    }
+/// Recent log records (the last `limit`, or all that are kept), oldest first.
+#[command]
+fn get_logs(limit: Option<usize>) -> Vec<RustLog> {
+    logging::recent(limit)
+}
 
-    Ok(())
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    logging::init();
+    info!("Starting application");
+    tauri::Builder::default()
+        .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            logging::attach(app.handle().clone());
+            app.manage(ServerState::default());
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            start_server,
+            stop_server,
+            server_status,
+            parse_pairing,
+            discover_receivers,
+            listen_for_beacons,
+            get_logs
+        ])
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
 }
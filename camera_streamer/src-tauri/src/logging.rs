@@ -0,0 +1,188 @@
+#![warn(clippy::all,
+clippy::pedantic,
+clippy::perf,
+clippy::nursery,
+// clippy::cargo,
+clippy::unwrap_used,
+clippy::expect_used)]
+
+//! `log` backend for the app: every record goes to stdout (logcat on Android),
+//! to a ring buffer the UI can fetch with `get_logs`, and to the UI as a
+//! `rust-log` event.
+//!
+//! Per-connection messages carry the connection id as a `conn` key-value:
+//! `log::info!(conn = conn_id; "...")`.
+
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock, PoisonError,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{kv::Key, Level, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+/// Entries kept for `get_logs`; older ones are dropped.
+const LOG_BUFFER_CAPACITY: usize = 500;
+/// Our own modules log from this level, dependencies (tauri, tungstenite...) only from `Warn`.
+const APP_LEVEL: Level = Level::Debug;
+const DEPENDENCY_LEVEL: Level = Level::Warn;
+const APP_TARGET: &str = env!("CARGO_CRATE_NAME");
+
+/// One log record, as stored in the ring buffer and sent with `rust-log` events.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RustLog {
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    pub level: String,
+    pub target: String,
+    /// Bridge connection the message is about, if any
+    pub conn_id: Option<u64>,
+    pub log_message: String,
+}
+
+struct UiLogger {
+    app: OnceLock<AppHandle>,
+    buffer: Mutex<VecDeque<RustLog>>,
+}
+
+static LOGGER: UiLogger = UiLogger {
+    app: OnceLock::new(),
+    buffer: Mutex::new(VecDeque::new()),
+};
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// Set while a record is being handled, so logs emitted by tauri while
+    /// forwarding it don't recurse back into the logger.
+    static IN_LOGGER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Installs the logger. Records are buffered until [`attach`] provides the app handle.
+pub fn init() {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Debug);
+    }
+}
+
+/// Starts forwarding records to the UI.
+pub fn attach(app: AppHandle) {
+    let _ = LOGGER.app.set(app);
+}
+
+/// Id for a new bridge connection, to tag its log records with.
+pub fn next_connection_id() -> u64 {
+    NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The most recent records, oldest first; at most `limit` of them when given.
+pub fn recent(limit: Option<usize>) -> Vec<RustLog> {
+    let buffer = LOGGER.buffer.lock().unwrap_or_else(PoisonError::into_inner);
+    let skip = limit.map_or(0, |limit| buffer.len().saturating_sub(limit));
+    buffer.iter().skip(skip).cloned().collect()
+}
+
+impl Log for UiLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let max = if metadata.target().starts_with(APP_TARGET) {
+            APP_LEVEL
+        } else {
+            DEPENDENCY_LEVEL
+        };
+        metadata.level() <= max
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) || IN_LOGGER.with(|busy| busy.replace(true)) {
+            return;
+        }
+
+        let entry = RustLog {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX)),
+            level: record.level().to_string(),
+            target: record.target().to_string(),
+            conn_id: record
+                .key_values()
+                .get(Key::from_str("conn"))
+                .and_then(|v| v.to_u64()),
+            log_message: record.args().to_string(),
+        };
+
+        write_console(record.level(), &entry);
+
+        {
+            let mut buffer = self.buffer.lock().unwrap_or_else(PoisonError::into_inner);
+            if buffer.len() == LOG_BUFFER_CAPACITY {
+                buffer.pop_front();
+            }
+            buffer.push_back(entry.clone());
+        }
+
+        // Not while holding the buffer: the UI may call get_logs meanwhile
+        if let Some(app) = self.app.get() {
+            let _ = app.emit("rust-log", entry);
+        }
+
+        IN_LOGGER.with(|busy| busy.set(false));
+    }
+
+    fn flush(&self) {}
+}
+
+fn format_line(entry: &RustLog) -> String {
+    let conn = entry
+        .conn_id
+        .map(|id| format!("[conn {id}] "))
+        .unwrap_or_default();
+    format!(
+        "{}.{:03} {:<5} {}: {conn}{}",
+        entry.timestamp_ms / 1000,
+        entry.timestamp_ms % 1000,
+        entry.level,
+        entry.target,
+        entry.log_message
+    )
+}
+
+#[cfg(not(target_os = "android"))]
+fn write_console(_level: Level, entry: &RustLog) {
+    println!("{}", format_line(entry));
+}
+
+/// stdout goes nowhere in an Android app, logcat is where `adb logcat` looks.
+#[cfg(target_os = "android")]
+fn write_console(level: Level, entry: &RustLog) {
+    use std::ffi::{c_char, c_int, CString};
+
+    #[link(name = "log")]
+    extern "C" {
+        fn __android_log_write(prio: c_int, tag: *const c_char, text: *const c_char) -> c_int;
+    }
+
+    // android/log.h priorities
+    let prio = match level {
+        Level::Trace => 2,
+        Level::Debug => 3,
+        Level::Info => 4,
+        Level::Warn => 5,
+        Level::Error => 6,
+    };
+    let (Ok(tag), Ok(text)) = (
+        CString::new("camera_streamer"),
+        CString::new(format_line(entry).replace('\0', " ")),
+    ) else {
+        return;
+    };
+    // SAFETY: both pointers are valid NUL-terminated strings for the duration of the call
+    unsafe {
+        __android_log_write(prio, tag.as_ptr(), text.as_ptr());
+    }
+}
@@ -0,0 +1,82 @@
+#![warn(clippy::all,
+clippy::pedantic,
+clippy::perf,
+clippy::nursery,
+// clippy::cargo,
+clippy::unwrap_used,
+clippy::expect_used)]
+
+//! Pairing with a receiver from the QR code it prints at startup.
+
+use std::net::{IpAddr, SocketAddr};
+
+use camstream_protocol::{Pairing, PROTOCOL_VERSION};
+use if_addrs::IfAddr;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+/// [`StartOptions`](crate::StartOptions) fields taken from a pairing code,
+/// named as the command expects them so the frontend can pass them straight on.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PairingSettings {
+    pub tcp_addr: String,
+    pub psk: Option<String>,
+    pub receiver_fingerprint: Option<String>,
+}
+
+/// Reads a scanned pairing code and picks which of the receiver's addresses to use.
+pub fn settings(code: &str) -> Result<PairingSettings, String> {
+    let pairing: Pairing = code
+        .parse()
+        .map_err(|err| format!("Invalid pairing code: {err}"))?;
+    if pairing.version != PROTOCOL_VERSION {
+        return Err(format!(
+            "The receiver speaks protocol version {}, this app speaks {PROTOCOL_VERSION}",
+            pairing.version
+        ));
+    }
+    let ip = pick_address(&pairing.addresses)
+        .ok_or_else(|| "The pairing code has no receiver address".to_string())?;
+    let tcp_addr = SocketAddr::new(ip, pairing.port).to_string();
+    info!("Paired with the receiver at {tcp_addr}");
+    Ok(PairingSettings {
+        tcp_addr,
+        psk: pairing.psk,
+        receiver_fingerprint: pairing
+            .fingerprint
+            .map(|fingerprint| fingerprint.to_string()),
+    })
+}
+
+/// The first address on a network this phone is on, or else the first one:
+/// a receiver with a VPN or a docker bridge lists addresses the phone can't reach.
+fn pick_address(addresses: &[IpAddr]) -> Option<IpAddr> {
+    let interfaces = if_addrs::get_if_addrs().unwrap_or_else(|err| {
+        warn!("Error listing network interfaces: {err}");
+        Vec::new()
+    });
+    addresses
+        .iter()
+        .copied()
+        .find(|&ip| {
+            interfaces
+                .iter()
+                .any(|iface| !iface.is_loopback() && same_network(&iface.addr, ip))
+        })
+        .or_else(|| addresses.first().copied())
+}
+
+fn same_network(local: &IfAddr, remote: IpAddr) -> bool {
+    match (local, remote) {
+        (IfAddr::V4(local), IpAddr::V4(remote)) => {
+            let mask = u32::from(local.netmask);
+            u32::from(local.ip) & mask == u32::from(remote) & mask
+        }
+        (IfAddr::V6(local), IpAddr::V6(remote)) => {
+            let mask = u128::from(local.netmask);
+            u128::from(local.ip) & mask == u128::from(remote) & mask
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,259 @@
+#![warn(clippy::all,
+clippy::pedantic,
+clippy::perf,
+clippy::nursery,
+// clippy::cargo,
+clippy::unwrap_used,
+clippy::expect_used)]
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tungstenite::protocol::frame::coding::CloseCode;
+
+use crate::simple_stream::BridgeConfig;
+use crate::{logging, simple_stream, ServerError};
+
+/// Pause after a failed accept, so a persistent error doesn't spin the loop.
+const ACCEPT_RETRY: Duration = Duration::from_millis(50);
+
+/// What `server_status` reports to the UI.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ServerStatus {
+    Stopped,
+    Running {
+        address: String,
+        port: u16,
+        clients: usize,
+    },
+}
+
+/// Payload of the `client-connected` / `client-disconnected` events.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ClientEvent {
+    pub peer_addr: String,
+}
+
+/// Tauri-managed handle on the WebSocket bridge, so it can be stopped and started again.
+#[derive(Default)]
+pub struct ServerState {
+    server: Mutex<Option<RunningServer>>,
+}
+
+impl ServerState {
+    /// Binds `listen_addr` and starts the accept loop on it; clients are bridged
+    /// to the receiver as `config` says. Returns the bound address once the
+    /// listener is ready.
+    pub fn start(
+        &self,
+        app: AppHandle,
+        listen_addr: SocketAddr,
+        config: BridgeConfig,
+    ) -> Result<SocketAddr, String> {
+        let mut server = self.server.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(running) = server.as_ref() {
+            if !running.task.inner().is_finished() {
+                return Err("Server is already running, stop it first".to_string());
+            }
+        }
+
+        let (listener, bound_addr) =
+            bind(listen_addr).map_err(|err| format!("Cannot listen on {listen_addr}: {err}"))?;
+        info!("WebSocket server listening on: {bound_addr}");
+
+        let shared = Arc::new(Shared {
+            cancel: CancellationToken::new(),
+            bound_addr,
+            clients: TaskTracker::new(),
+        });
+
+        let task_shared = Arc::clone(&shared);
+        let task = tauri::async_runtime::spawn(async move {
+            if let Err(err) = accept_loop(&app, listener, &config, &task_shared).await {
+                let message = format!("WebSocket server on {bound_addr} failed: {err}");
+                error!("{message}");
+                if let Err(err) = app.emit("server-error", ServerError { message }) {
+                    warn!("Error emitting server-error: {err}");
+                }
+            }
+            // Nothing gets tracked past this point; wait for the clients still open
+            task_shared.clients.close();
+            task_shared.clients.wait().await;
+            if let Err(err) = app.emit("server-stopped", ()) {
+                warn!("Error emitting server-stopped: {err}");
+            }
+        });
+
+        *server = Some(RunningServer { shared, task });
+        Ok(bound_addr)
+    }
+
+    /// Detaches the running server, if any, so it can be shut down without
+    /// holding the state lock. See [`RunningServer::shutdown`].
+    pub fn take(&self) -> Option<RunningServer> {
+        self.server
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        let server = self.server.lock().unwrap_or_else(PoisonError::into_inner);
+        match server.as_ref() {
+            Some(running) if !running.task.inner().is_finished() => running.shared.status(),
+            _ => ServerStatus::Stopped,
+        }
+    }
+}
+
+/// State shared between the accept task and the handle kept in [`ServerState`].
+struct Shared {
+    /// Stops the accept loop and, through child tokens, every client session
+    cancel: CancellationToken,
+    bound_addr: SocketAddr,
+    clients: TaskTracker,
+}
+
+impl Shared {
+    fn status(&self) -> ServerStatus {
+        ServerStatus::Running {
+            address: self.bound_addr.ip().to_string(),
+            port: self.bound_addr.port(),
+            clients: self.clients.len(),
+        }
+    }
+}
+
+/// A started server, detached from [`ServerState`].
+pub struct RunningServer {
+    shared: Arc<Shared>,
+    task: JoinHandle<()>,
+}
+
+impl RunningServer {
+    /// Stops accepting, closes every client (WebSocket close frame, then the
+    /// TCP side) and waits until they are all done.
+    pub async fn shutdown(self) {
+        self.shared.cancel.cancel();
+        if let Err(err) = self.task.await {
+            error!("WebSocket server task failed: {err}");
+        }
+    }
+}
+
+fn bind(listen_addr: SocketAddr) -> Result<(std::net::TcpListener, SocketAddr), std::io::Error> {
+    // Bound here rather than in the accept task so a busy port fails `start` itself
+    let listener = std::net::TcpListener::bind(listen_addr)?;
+    // Required before handing it to tokio
+    listener.set_nonblocking(true)?;
+    let bound_addr = listener.local_addr()?;
+    Ok((listener, bound_addr))
+}
+
+async fn accept_loop(
+    app: &AppHandle,
+    listener: std::net::TcpListener,
+    config: &BridgeConfig,
+    shared: &Shared,
+) -> Result<(), std::io::Error> {
+    let server = TcpListener::from_std(listener)?;
+    loop {
+        let accepted = tokio::select! {
+            () = shared.cancel.cancelled() => break,
+            accepted = server.accept() => accepted,
+        };
+        let (stream, peer_addr) = match accepted {
+            Ok(accepted) => accepted,
+            // A client giving up mid-handshake is no reason to stop serving the others
+            Err(err) => {
+                warn!("Error accepting connection: {err}");
+                tokio::time::sleep(ACCEPT_RETRY).await;
+                continue;
+            }
+        };
+        let conn_id = logging::next_connection_id();
+        info!(conn = conn_id; "New connection from: {peer_addr}");
+
+        let client = serve_client(
+            app.clone(),
+            config.clone(),
+            stream,
+            peer_addr,
+            conn_id,
+            shared.cancel.clone(),
+        );
+        tauri::async_runtime::spawn(shared.clients.track_future(client));
+    }
+
+    Ok(())
+}
+
+/// One client session, from its connection to its last byte.
+async fn serve_client(
+    app: AppHandle,
+    config: BridgeConfig,
+    stream: TcpStream,
+    peer_addr: SocketAddr,
+    conn_id: u64,
+    cancel: CancellationToken,
+) {
+    emit_client_event(&app, "client-connected", peer_addr);
+    if let Err(err) = bridge_client(&app, &config, stream, peer_addr, conn_id, &cancel).await {
+        warn!(conn = conn_id; "Client {peer_addr} failed: {err}");
+    }
+    emit_client_event(&app, "client-disconnected", peer_addr);
+}
+
+async fn bridge_client(
+    app: &AppHandle,
+    config: &BridgeConfig,
+    stream: TcpStream,
+    peer_addr: SocketAddr,
+    conn_id: u64,
+    cancel: &CancellationToken,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // The page authenticates and announces its stream before the receiver is
+    // dialed: the receiver serves one sender at a time, on a short handshake
+    // deadline, and a stray local client mustn't hold that slot
+    let page = tokio::select! {
+        () = cancel.cancelled() => return Ok(()),
+        page = simple_stream::open_page(stream, peer_addr, conn_id, config.psk.as_ref()) => page?,
+    };
+    match config.upstream.connect(app, conn_id, cancel).await {
+        Ok(receiver) => {
+            simple_stream::handle_client(app, config, receiver, page, peer_addr, conn_id, cancel)
+                .await
+        }
+        Err(err) => {
+            let reason = format!("receiver at {} unreachable: {err}", config.upstream.addr);
+            simple_stream::close_client(
+                page.websocket,
+                peer_addr,
+                conn_id,
+                CloseCode::Again,
+                &reason,
+            )
+            .await;
+            Ok(())
+        }
+    }
+}
+
+fn emit_client_event(app: &AppHandle, event: &str, peer_addr: SocketAddr) {
+    let payload = ClientEvent {
+        peer_addr: peer_addr.to_string(),
+    };
+    if let Err(err) = app.emit(event, payload) {
+        warn!("Error emitting {event}: {err}");
+    }
+}
@@ -6,82 +6,718 @@ clippy::nursery,
 clippy::unwrap_used,
 clippy::expect_used)]
 
-use serde::{Deserialize, Serialize};
-use std::io::Write;
-use std::net::TcpStream;
-use tungstenite::accept;
-use tungstenite::Message;
+use camstream_protocol::{
+    tokio_io, AuthResponse, Challenge, Frame, Hello, HelloAck, PreSharedKey, StreamParams, MAGIC,
+    PROTOCOL_VERSION,
+};
+use futures_util::{SinkExt, StreamExt};
+use log::{info, trace, warn};
+use serde::Deserialize;
+use std::io;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_tungstenite::{accept_async, WebSocketStream};
+use tokio_util::sync::CancellationToken;
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::CloseFrame;
+use tungstenite::{Bytes, Message};
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct RustLog {
-    pub log_message: String,
+use crate::upstream::{ReceiverStream, Upstream};
+use crate::webm::{ClusterReplay, Fragment, WebmScanner};
+
+/// A page that opened the socket but never finishes the handshake (or doesn't
+/// answer the challenge) is dropped after this.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest reason a WebSocket close frame can carry, in bytes.
+const MAX_CLOSE_REASON: usize = 123;
+/// How long the page gets to announce its stream before a default hello is used.
+const PAGE_HELLO_TIMEOUT: Duration = Duration::from_secs(3);
+/// Messages waiting for the receiver side. When it's full, reading from the
+/// phone pauses; the latency budget normally kicks in well before that.
+const QUEUE_CAPACITY: usize = 32;
+/// A receiver that takes no data for this long is taken as stalled and dialed again.
+const RECEIVER_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Minimum time between two `bridge-stats` events for a client.
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+pub const DEFAULT_LATENCY_BUDGET: Duration = Duration::from_millis(500);
+
+/// What every bridged client is set up with.
+#[derive(Debug, Clone)]
+pub struct BridgeConfig {
+    pub upstream: Upstream,
+    /// How long data may wait for the receiver before whole clusters get dropped
+    pub latency_budget: Duration,
+    /// Pages must prove they know it before anything reaches the receiver,
+    /// which gets the same proof in its handshake
+    pub psk: Option<PreSharedKey>,
 }
 
-pub fn handle_client(
-    tcp_addr: &str,
-    stream: TcpStream,
-    peer_addr: std::net::SocketAddr,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut websocket = match accept(stream) {
-        Ok(ws) => ws,
-        Err(e) => {
-            println!("Error during handshake with {}: {}", peer_addr, e);
-            return Err("couldn't open websocket".into());
+/// A client's WebSocket, once accepted.
+pub type ClientSocket = WebSocketStream<TcpStream>;
+
+/// A page that authenticated and announced its stream (see [`open_page`]),
+/// ready to be bridged.
+pub struct Page {
+    pub websocket: ClientSocket,
+    pub hello: Hello,
+    /// Media the page sent before (or instead of) its hello
+    pub early: Option<Bytes>,
+}
+
+/// Payload of the `bridge-stats` event: how well the receiver side of a client keeps up.
+pub type BridgeStats = camstream_protocol::Stats;
+
+/// A binary message from the phone, waiting for the receiver side.
+struct Queued {
+    received_at: Instant,
+    data: Bytes,
+}
+
+/// The page's first text message: its [`Hello`], minus the protocol version
+/// the bridge fills in.
+#[derive(Deserialize)]
+struct PageHello {
+    device_name: String,
+    #[serde(flatten)]
+    params: StreamParams,
+}
+
+impl From<PageHello> for Hello {
+    fn from(page: PageHello) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            device_name: page.device_name,
+            params: page.params,
         }
-    };
+    }
+}
+
+/// Announced for a page that streams without saying what: the `MediaRecorder`
+/// defaults the frontend uses.
+fn default_hello() -> Hello {
+    Hello {
+        version: PROTOCOL_VERSION,
+        device_name: "unknown device".to_string(),
+        params: StreamParams {
+            codec: "h264".to_string(),
+            container: "webm".to_string(),
+            width: 0,
+            height: 0,
+            fps: 0.0,
+            orientation: 0,
+        },
+    }
+}
 
-    println!("WebSocket connection established with {}", peer_addr);
+/// Bridges an opened `page` to `receiver`, an already open connection to the
+/// receiver (see [`Upstream::connect`]).
+///
+/// If that connection drops, the receiver is dialed again and the stream
+/// resumes from the start of the current Cluster. The page's hello is passed
+/// on to the receiver, and its answer back to the page, before any media flows.
+///
+/// Reading from the phone and writing to the receiver run concurrently, with
+/// a bounded queue in between, so a slow link doesn't stall the phone side.
+/// Returns once either side is done or `cancel` fires; fails, telling the page
+/// why in the close frame, if the receiver is lost for good.
+pub async fn handle_client(
+    app: &AppHandle,
+    config: &BridgeConfig,
+    mut receiver: ReceiverStream,
+    page: Page,
+    peer_addr: std::net::SocketAddr,
+    conn_id: u64,
+    cancel: &CancellationToken,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Page {
+        mut websocket,
+        hello,
+        early,
+    } = page;
+    introduce(
+        &mut websocket,
+        &mut receiver,
+        &hello,
+        config.psk.as_ref(),
+        peer_addr,
+        conn_id,
+    )
+    .await?;
 
-    // Connect TCP
-    let mut tcp_stream = TcpStream::connect(tcp_addr)?;
-    println!("Tcp Connected =========================================================");
-    println!("Tcp Connected =========================================================");
-    println!("Tcp Connected =========================================================");
+    // Ends both halves: fired by the writer when the receiver is gone for
+    // good, and with `cancel` when the server stops
+    let session = cancel.child_token();
+    // Why the writer gave up on the receiver, for the page's close frame
+    let lost = OnceLock::new();
+    let (to_writer, from_reader) = mpsc::channel(QUEUE_CAPACITY);
+    let link = ReceiverLink::new(app, config, conn_id, hello, receiver);
 
-    loop {
-        match websocket.read() {
-            Ok(Message::Text(text)) => {
-                println!("Received from {}: {}", peer_addr, text);
+    let reader = async {
+        // Media that arrived before (or instead of) the page's hello goes first
+        let mut early = early.map(|data| Ok(Message::Binary(data)));
+        loop {
+            let message = match early.take() {
+                Some(message) => Some(message),
+                None => tokio::select! {
+                    () = session.cancelled() => break,
+                    message = websocket.next() => message,
+                },
+            };
 
-                // Echo the message back
-                let response = format!("Echo: {}", text);
-                if let Err(e) = websocket.send(Message::Text(response.into())) {
-                    println!("Error sending to {}: {}", peer_addr, e);
-                    break;
+            match message {
+                Some(Ok(Message::Text(text))) => {
+                    trace!(conn = conn_id; "Received from {}: {}", peer_addr, text);
+
+                    // Echo the message back
+                    let response = format!("Echo: {}", text);
+                    if let Err(e) = websocket.send(Message::Text(response.into())).await {
+                        warn!(conn = conn_id; "Error sending to {}: {}", peer_addr, e);
+                        break;
+                    }
                 }
-            }
-            Ok(Message::Binary(data)) => {
-                println!("Received {} bytes from {}", data.len(), peer_addr);
+                Some(Ok(Message::Binary(data))) => {
+                    trace!(conn = conn_id; "Received {} bytes from {}", data.len(), peer_addr);
 
-                // Echo binary data back
-                if let Err(e) = tcp_stream.write_all(&data.slice(..)) {
-                    println!("Error sending binary to {}: {}", peer_addr, e);
+                    let queued = Queued {
+                        received_at: Instant::now(),
+                        data,
+                    };
+                    // Waits while the queue is full; fails once the writer is gone
+                    if to_writer.send(queued).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | None => {
+                    info!(conn = conn_id; "Client {} disconnected", peer_addr);
+                    break;
+                }
+                Some(Ok(Message::Ping(data))) => {
+                    if let Err(e) = websocket.send(Message::Pong(data)).await {
+                        warn!(conn = conn_id; "Error sending pong to {}: {}", peer_addr, e);
+                        break;
+                    }
+                }
+                Some(Ok(Message::Pong(_) | Message::Frame(_))) => {}
+                Some(Err(e)) => {
+                    warn!(conn = conn_id; "Error reading from {}: {}", peer_addr, e);
                     break;
                 }
             }
-            Ok(Message::Close(_)) => {
-                println!("Client {} disconnected", peer_addr);
+        }
+        // Lets the writer finish what's queued and stop
+        drop(to_writer);
+        // Best effort from here on: the client may already be gone
+        if cancel.is_cancelled() {
+            info!(conn = conn_id; "Server stopping, closing connection with {peer_addr}");
+            let _ = websocket.close(None).await;
+        } else if let Some(reason) = lost.get() {
+            let close = close_frame(CloseCode::Again, &format!("receiver lost: {reason}"));
+            let _ = websocket.close(Some(close)).await;
+        }
+    };
+    let writer = async {
+        let result = link.run(from_reader, cancel).await;
+        // Set before the reader wakes up to the cancellation or the closed queue
+        if let Err(err) = &result {
+            let _ = lost.set(err.to_string());
+        }
+        session.cancel();
+        result
+    };
+
+    let ((), result) = tokio::join!(reader, writer);
+    info!(conn = conn_id; "Connection with {} closed", peer_addr);
+    result.map_err(|err| format!("lost the receiver: {err}").into())
+}
+
+/// Completes the WebSocket handshake with a new client, has the page prove it
+/// knows the pre-shared key if one is set, and waits for its hello: all before
+/// the receiver is dialed.
+pub async fn open_page(
+    stream: TcpStream,
+    peer_addr: std::net::SocketAddr,
+    conn_id: u64,
+    psk: Option<&PreSharedKey>,
+) -> Result<Page, Box<dyn std::error::Error + Send + Sync>> {
+    let mut websocket = accept_client(stream, peer_addr, conn_id, psk).await?;
+    let (hello, early) = page_hello(&mut websocket, conn_id).await;
+    Ok(Page {
+        websocket,
+        hello,
+        early,
+    })
+}
+
+/// Completes the WebSocket handshake with a new client and, with a pre-shared
+/// key set, has the page prove it knows it. A page that can't is told so in
+/// the close frame.
+async fn accept_client(
+    stream: TcpStream,
+    peer_addr: std::net::SocketAddr,
+    conn_id: u64,
+    psk: Option<&PreSharedKey>,
+) -> Result<ClientSocket, Box<dyn std::error::Error + Send + Sync>> {
+    let mut websocket = match tokio::time::timeout(HANDSHAKE_TIMEOUT, accept_async(stream)).await {
+        Ok(Ok(ws)) => ws,
+        Ok(Err(e)) => {
+            warn!(conn = conn_id; "Error during handshake with {}: {}", peer_addr, e);
+            return Err("couldn't open websocket".into());
+        }
+        Err(_) => {
+            warn!(conn = conn_id; "Handshake with {peer_addr} timed out");
+            return Err("couldn't open websocket".into());
+        }
+    };
+
+    info!(conn = conn_id; "WebSocket connection established with {}", peer_addr);
+
+    if let Some(psk) = psk {
+        if let Err(reason) = authenticate_page(&mut websocket, psk).await {
+            warn!(conn = conn_id; "Rejecting {peer_addr}, authentication failed: {reason}");
+            close_client(
+                websocket,
+                peer_addr,
+                conn_id,
+                CloseCode::Policy,
+                "authentication failed",
+            )
+            .await;
+            return Err(format!("authentication failed: {reason}").into());
+        }
+        info!(conn = conn_id; "{peer_addr} authenticated");
+    }
+    Ok(websocket)
+}
+
+/// Challenges the page to prove it knows `psk`; the error is why it didn't.
+async fn authenticate_page(websocket: &mut ClientSocket, psk: &PreSharedKey) -> Result<(), String> {
+    let challenge = Challenge::new().map_err(|err| format!("no nonce: {err}"))?;
+    let json = serde_json::to_string(&challenge).map_err(|err| err.to_string())?;
+    websocket
+        .send(Message::Text(json.into()))
+        .await
+        .map_err(|err| format!("couldn't send the challenge: {err}"))?;
+
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, websocket.next()).await {
+        Ok(Some(Ok(Message::Text(text)))) => match serde_json::from_str::<AuthResponse>(&text) {
+            Ok(response) if psk.verify(&challenge, &response) => Ok(()),
+            Ok(_) => Err("wrong pre-shared key".to_string()),
+            Err(_) => Err("the page didn't answer the challenge".to_string()),
+        },
+        Ok(Some(Ok(_))) => Err("the page didn't answer the challenge".to_string()),
+        Ok(Some(Err(err))) => Err(err.to_string()),
+        Ok(None) => Err("closed before answering the challenge".to_string()),
+        Err(_) => Err("no answer to the challenge in time".to_string()),
+    }
+}
+
+/// Waits for the page's hello. A page that sends media first, or nothing in
+/// time, gets [`default_hello`]; media it already sent is handed back.
+async fn page_hello(websocket: &mut ClientSocket, conn_id: u64) -> (Hello, Option<Bytes>) {
+    match tokio::time::timeout(PAGE_HELLO_TIMEOUT, websocket.next()).await {
+        Ok(Some(Ok(Message::Text(text)))) => match serde_json::from_str::<PageHello>(&text) {
+            Ok(page) => return (page.into(), None),
+            Err(err) => warn!(conn = conn_id; "Ignoring malformed hello from the page: {err}"),
+        },
+        Ok(Some(Ok(Message::Binary(data)))) => {
+            info!(conn = conn_id; "Page sent media without a hello, using defaults");
+            return (default_hello(), Some(data));
+        }
+        // Anything else (control frames, a close) is picked up by the reader loop
+        Ok(_) => {}
+        Err(_) => info!(conn = conn_id; "No hello from the page, using defaults"),
+    }
+    (default_hello(), None)
+}
+
+/// Hands `hello` to the receiver and its answer to the page. Fails, closing the
+/// WebSocket, if the receiver refuses the stream or doesn't answer.
+///
+/// A receiver from before the protocol never answers: its FFmpeg probe chokes
+/// on the hello and it exits, so the page is told to update it.
+async fn introduce(
+    websocket: &mut ClientSocket,
+    receiver: &mut ReceiverStream,
+    hello: &Hello,
+    psk: Option<&PreSharedKey>,
+    peer_addr: std::net::SocketAddr,
+    conn_id: u64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let ack = match handshake(receiver, hello, psk).await {
+        Ok(ack) => ack,
+        Err(err) if is_unanswered(&err) => {
+            let err = io::Error::new(
+                err.kind(),
+                format!(
+                    "{err}; the receiver may be too old for protocol version {PROTOCOL_VERSION}, update it"
+                ),
+            );
+            warn!(conn = conn_id; "Handshake with the receiver failed: {err}");
+            let close = close_frame(
+                CloseCode::Again,
+                "the receiver didn't answer the handshake, it may be too old: update it",
+            );
+            let _ = websocket.close(Some(close)).await;
+            return Err(err.into());
+        }
+        Err(err) => {
+            warn!(conn = conn_id; "Handshake with the receiver failed: {err}");
+            let close = close_frame(
+                CloseCode::Again,
+                &format!("receiver handshake failed: {err}"),
+            );
+            let _ = websocket.close(Some(close)).await;
+            return Err(err.into());
+        }
+    };
+    // The page learns what the receiver makes of its stream, or why it's refused
+    if let Ok(json) = serde_json::to_string(&ack) {
+        if let Err(e) = websocket.send(Message::Text(json.into())).await {
+            warn!(conn = conn_id; "Error sending the receiver's answer to {peer_addr}: {e}");
+        }
+    }
+    if !ack.accepted {
+        let reason = ack.reason.unwrap_or_default();
+        warn!(conn = conn_id; "Receiver refused the stream from {peer_addr}: {reason}");
+        let close = close_frame(
+            CloseCode::Policy,
+            &format!("refused by the receiver: {reason}"),
+        );
+        let _ = websocket.close(Some(close)).await;
+        return Err(format!("receiver refused the stream: {reason}").into());
+    }
+    info!(
+        conn = conn_id;
+        "Receiver accepted {} from {peer_addr}, output {}x{} @ {} fps",
+        hello.device_name,
+        ack.params.width,
+        ack.params.height,
+        ack.params.fps
+    );
+    Ok(())
+}
+
+/// The handshake got no answer at all, as from a receiver that doesn't know the protocol.
+fn is_unanswered(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// Opens the framed protocol on a fresh receiver connection and returns its
+/// answer to `hello`, answering its challenge with `psk` on the way if it has one.
+async fn handshake(
+    receiver: &mut ReceiverStream,
+    hello: &Hello,
+    psk: Option<&PreSharedKey>,
+) -> io::Result<HelloAck> {
+    let exchange = async {
+        receiver.write_all(&MAGIC).await?;
+        tokio_io::write_frame(receiver, &Frame::Hello(hello.clone())).await?;
+        let mut answer = tokio_io::read_frame(receiver).await?;
+        if let Some(Frame::Challenge(challenge)) = &answer {
+            let Some(psk) = psk else {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "the receiver requires a pre-shared key and none is set",
+                ));
+            };
+            tokio_io::write_frame(receiver, &Frame::Auth(psk.respond(challenge))).await?;
+            answer = tokio_io::read_frame(receiver).await?;
+        }
+        match answer {
+            Some(Frame::HelloAck(ack)) => Ok(ack),
+            Some(other) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected the receiver's answer, got {other:?}"),
+            )),
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "receiver closed the connection during the handshake",
+            )),
+        }
+    };
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, exchange)
+        .await
+        .unwrap_or_else(|_| {
+            Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "receiver didn't answer the handshake",
+            ))
+        })
+}
+
+/// The receiver side of a bridged client. Keeps enough of the `WebM` stream
+/// (its init segment and the Cluster being sent) to start a fresh decoder
+/// after a reconnect, and drops whole clusters when the data it gets is older
+/// than the latency budget.
+struct ReceiverLink<'a> {
+    app: &'a AppHandle,
+    config: &'a BridgeConfig,
+    conn_id: u64,
+    /// Announced again on every reconnect
+    hello: Hello,
+    receiver: ReceiverStream,
+    scanner: WebmScanner,
+    replay: ClusterReplay,
+    /// Reconnected without the current Cluster, dropping data until the next one starts
+    resyncing: bool,
+    /// The current Cluster came in too late and is being skipped
+    dropping: bool,
+    stats: BridgeStats,
+    last_report: Option<Instant>,
+}
+
+impl<'a> ReceiverLink<'a> {
+    fn new(
+        app: &'a AppHandle,
+        config: &'a BridgeConfig,
+        conn_id: u64,
+        hello: Hello,
+        receiver: ReceiverStream,
+    ) -> Self {
+        Self {
+            app,
+            config,
+            conn_id,
+            hello,
+            receiver,
+            scanner: WebmScanner::new(),
+            replay: ClusterReplay::default(),
+            resyncing: false,
+            dropping: false,
+            stats: BridgeStats {
+                conn_id,
+                ..BridgeStats::default()
+            },
+            last_report: None,
+        }
+    }
+
+    /// Writes queued messages until the reader hangs up, the receiver is lost
+    /// for good or `cancel` fires.
+    async fn run(
+        mut self,
+        mut queue: mpsc::Receiver<Queued>,
+        cancel: &CancellationToken,
+    ) -> io::Result<()> {
+        let mut result = Ok(());
+        loop {
+            let queued = tokio::select! {
+                () = cancel.cancelled() => break,
+                queued = queue.recv() => match queued {
+                    Some(queued) => queued,
+                    None => break,
+                },
+            };
+            // A write to a stalled receiver mustn't hold up a stopping server
+            result = tokio::select! {
+                () = cancel.cancelled() => break,
+                result = self.forward(&queued, cancel) => result,
+            };
+            if result.is_err() {
                 break;
             }
-            Ok(Message::Ping(data)) => {
-                if let Err(e) = websocket.send(Message::Pong(data)) {
-                    println!("Error sending pong to {}: {}", peer_addr, e);
-                    break;
+        }
+        self.report(true);
+        let _ = self.receiver.shutdown().await;
+        result
+    }
+
+    async fn forward(&mut self, queued: &Queued, cancel: &CancellationToken) -> io::Result<()> {
+        let lag = queued.received_at.elapsed();
+        self.stats.queue_latency_ms = u64::try_from(lag.as_millis()).unwrap_or(u64::MAX);
+        for fragment in self.scanner.feed(&queued.data) {
+            self.send(&fragment, lag, cancel).await?;
+        }
+        self.report(false);
+        Ok(())
+    }
+
+    async fn send(
+        &mut self,
+        fragment: &Fragment,
+        lag: Duration,
+        cancel: &CancellationToken,
+    ) -> io::Result<()> {
+        if fragment.stream_start {
+            // Nothing after an init segment decodes without it, and a fresh
+            // decoder can start from it
+            self.dropping = false;
+            self.resyncing = false;
+        } else if fragment.cluster_start {
+            self.keep_up(lag);
+        }
+        if self.dropping {
+            self.stats.bytes_dropped += fragment.bytes.len() as u64;
+            return Ok(());
+        }
+
+        let mut reconnected = false;
+        loop {
+            if self.resyncing {
+                if !fragment.cluster_start {
+                    self.stats.bytes_dropped += fragment.bytes.len() as u64;
+                    return Ok(());
                 }
+                self.resyncing = false;
+                info!(conn = self.conn_id; "Resuming the stream at the next cluster");
             }
-            Ok(Message::Pong(_)) => {
-                // Handle pong if needed
-            }
-            Ok(Message::Frame(_)) => {
-                // Handle pong if needed
+            match write_to(&mut self.receiver, &fragment.bytes).await {
+                Ok(()) => {
+                    self.replay.record(fragment);
+                    self.stats.bytes_sent += fragment.bytes.len() as u64;
+                    if fragment.cluster_start {
+                        self.stats.clusters_sent += 1;
+                    }
+                    return Ok(());
+                }
+                // Once per fragment: a receiver that takes the connection and
+                // drops it again isn't going to take this one either
+                Err(err) if reconnected => return Err(err),
+                Err(err) => {
+                    warn!(
+                        conn = self.conn_id;
+                        "Error writing to the receiver: {err}, reconnecting"
+                    );
+                    self.reconnect(fragment, cancel).await?;
+                    reconnected = true;
+                }
             }
-            Err(e) => {
-                println!("Error reading from {}: {}", peer_addr, e);
-                break;
+        }
+    }
+
+    /// Decides, at a Cluster start, whether that Cluster is sent or skipped.
+    fn keep_up(&mut self, lag: Duration) {
+        let budget = self.config.latency_budget;
+        let behind = lag > budget;
+        if behind {
+            if !self.dropping {
+                warn!(
+                    conn = self.conn_id;
+                    "Receiver {} ms behind (budget {} ms), dropping clusters",
+                    lag.as_millis(),
+                    budget.as_millis()
+                );
             }
+            self.stats.clusters_dropped += 1;
+        } else if self.dropping {
+            info!(
+                conn = self.conn_id;
+                "Receiver caught up, {} clusters dropped so far", self.stats.clusters_dropped
+            );
         }
+        self.dropping = behind;
     }
 
-    println!("Connection with {} closed", peer_addr);
-    Ok(())
+    /// Opens a new connection to the receiver, repeats the handshake and
+    /// replays the init segment on it, then what was sent of the current
+    /// Cluster so that `next`, the fragment that failed, carries on from it.
+    async fn reconnect(&mut self, next: &Fragment, cancel: &CancellationToken) -> io::Result<()> {
+        let _ = self.receiver.shutdown().await;
+        // A new stream brings its own init segment
+        let init = if next.stream_start {
+            None
+        } else {
+            Some(self.scanner.init_segment().ok_or_else(|| {
+                io::Error::other("stream dropped before its first cluster, nothing to resume from")
+            })?)
+        };
+        self.receiver = self
+            .config
+            .upstream
+            .connect(self.app, self.conn_id, cancel)
+            .await?;
+        let ack = handshake(&mut self.receiver, &self.hello, self.config.psk.as_ref()).await?;
+        if !ack.accepted {
+            return Err(io::Error::other(format!(
+                "receiver refused the stream: {}",
+                ack.reason.unwrap_or_default()
+            )));
+        }
+        let Some(init) = init else {
+            return Ok(());
+        };
+        write_to(&mut self.receiver, init).await?;
+        if next.cluster_start {
+            info!(conn = self.conn_id; "Sent the {} byte init segment again", init.len());
+            return Ok(());
+        }
+        if let Some(cluster) = self.replay.cluster() {
+            write_to(&mut self.receiver, cluster).await?;
+            info!(
+                conn = self.conn_id;
+                "Sent the {} byte init segment and the {} bytes of the current cluster again",
+                init.len(),
+                cluster.len()
+            );
+        } else {
+            info!(
+                conn = self.conn_id;
+                "Sent the {} byte init segment again, waiting for the next cluster", init.len()
+            );
+            self.resyncing = true;
+        }
+        Ok(())
+    }
+
+    /// Emits `bridge-stats`, at most every [`STATS_INTERVAL`] unless `force`d.
+    fn report(&mut self, force: bool) {
+        let due = self
+            .last_report
+            .is_none_or(|last| last.elapsed() >= STATS_INTERVAL);
+        if !force && !due {
+            return;
+        }
+        self.last_report = Some(Instant::now());
+        if let Err(err) = self.app.emit("bridge-stats", self.stats.clone()) {
+            warn!("Error emitting bridge-stats: {err}");
+        }
+    }
+}
+
+/// Writes `bytes` to the receiver as a media frame. Fails if the receiver
+/// doesn't take them within [`RECEIVER_WRITE_TIMEOUT`].
+async fn write_to(receiver: &mut ReceiverStream, bytes: &[u8]) -> io::Result<()> {
+    tokio::time::timeout(
+        RECEIVER_WRITE_TIMEOUT,
+        tokio_io::write_media(receiver, bytes),
+    )
+    .await
+    .unwrap_or_else(|_| {
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "the receiver stopped taking data",
+        ))
+    })
+}
+
+/// Closes an accepted client's WebSocket, telling the page why.
+pub async fn close_client(
+    mut websocket: ClientSocket,
+    peer_addr: std::net::SocketAddr,
+    conn_id: u64,
+    code: CloseCode,
+    reason: &str,
+) {
+    // Best effort: the client may already be gone
+    let _ = websocket.close(Some(close_frame(code, reason))).await;
+    info!(conn = conn_id; "Closed {peer_addr}: {reason}");
+}
+
+/// `reason` cut to what fits in a close frame.
+fn close_frame(code: CloseCode, reason: &str) -> CloseFrame {
+    let mut end = reason.len().min(MAX_CLOSE_REASON);
+    while !reason.is_char_boundary(end) {
+        end -= 1;
+    }
+    CloseFrame {
+        code,
+        reason: reason[..end].to_string().into(),
+    }
 }
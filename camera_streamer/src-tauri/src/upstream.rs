@@ -0,0 +1,210 @@
+#![warn(clippy::all,
+clippy::pedantic,
+clippy::perf,
+clippy::nursery,
+// clippy::cargo,
+clippy::unwrap_used,
+clippy::expect_used)]
+
+//! Connection to the desktop receiver, made once a page has opened its
+//! WebSocket (authenticating first, with a pre-shared key) and announced its
+//! stream, so the receiver's only slot never waits on the page.
+
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+
+use camstream_protocol::SERVER_NAME;
+use log::{info, warn};
+use rustls::{pki_types::ServerName, ClientConfig};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{lookup_host, TcpStream};
+use tokio_rustls::TlsConnector;
+use tokio_util::sync::CancellationToken;
+
+/// A connection to the receiver, over TLS or not.
+pub trait ReceiverIo: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ReceiverIo for T {}
+
+pub type ReceiverStream = Box<dyn ReceiverIo>;
+
+/// How hard to try reaching the receiver.
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectPolicy {
+    /// Per attempt
+    pub connect_timeout: Duration,
+    /// Including the first one
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for ConnectPolicy {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(3),
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+        }
+    }
+}
+
+impl ConnectPolicy {
+    /// Wait before attempt `attempt + 1` (attempts count from 1): doubles each time, capped.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// Payload of the `receiver-status` event.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ReceiverStatus {
+    Connecting {
+        conn_id: u64,
+        attempt: u32,
+    },
+    Connected {
+        conn_id: u64,
+    },
+    Retrying {
+        conn_id: u64,
+        attempt: u32,
+        retry_in_secs: f64,
+        error: String,
+    },
+    Failed {
+        conn_id: u64,
+        error: String,
+    },
+}
+
+/// Desktop receiver address plus the policy used to reach it.
+#[derive(Debug, Clone)]
+pub struct Upstream {
+    pub addr: String,
+    pub policy: ConnectPolicy,
+    /// Speak TLS to a receiver started with `--tls`, trusting only its pinned
+    /// certificate (see [`crate::tls`]); plaintext when `None`
+    pub tls: Option<Arc<ClientConfig>>,
+}
+
+impl Upstream {
+    /// Connects to the receiver, retrying with exponential backoff. Gives up
+    /// early (with `Interrupted`) when `cancel` fires.
+    pub async fn connect(
+        &self,
+        app: &AppHandle,
+        conn_id: u64,
+        cancel: &CancellationToken,
+    ) -> io::Result<ReceiverStream> {
+        let max_attempts = self.policy.max_attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            emit_status(app, ReceiverStatus::Connecting { conn_id, attempt });
+            info!(
+                conn = conn_id;
+                "Connecting to the receiver at {} (attempt {attempt}/{max_attempts})", self.addr
+            );
+
+            let attempt_result = tokio::select! {
+                () = cancel.cancelled() => return Err(interrupted()),
+                result = self.try_connect() => result,
+            };
+            let err = match attempt_result {
+                Ok(stream) => {
+                    info!(conn = conn_id; "Connected to the receiver at {}", self.addr);
+                    emit_status(app, ReceiverStatus::Connected { conn_id });
+                    return Ok(stream);
+                }
+                Err(err) => err,
+            };
+
+            if attempt >= max_attempts {
+                warn!(conn = conn_id; "Giving up on the receiver at {}: {err}", self.addr);
+                emit_status(
+                    app,
+                    ReceiverStatus::Failed {
+                        conn_id,
+                        error: err.to_string(),
+                    },
+                );
+                return Err(err);
+            }
+
+            let wait = self.policy.backoff(attempt);
+            warn!(
+                conn = conn_id;
+                "Receiver at {} unreachable ({err}), retrying in {:.1} s",
+                self.addr,
+                wait.as_secs_f64()
+            );
+            emit_status(
+                app,
+                ReceiverStatus::Retrying {
+                    conn_id,
+                    attempt,
+                    retry_in_secs: wait.as_secs_f64(),
+                    error: err.to_string(),
+                },
+            );
+            tokio::select! {
+                () = cancel.cancelled() => return Err(interrupted()),
+                () = tokio::time::sleep(wait) => {}
+            }
+            attempt += 1;
+        }
+    }
+
+    /// One attempt, over every address `addr` resolves to.
+    async fn try_connect(&self) -> io::Result<ReceiverStream> {
+        let mut last_err = None;
+        for addr in lookup_host(&self.addr).await? {
+            match tokio::time::timeout(self.policy.connect_timeout, self.connect_to(addr)).await {
+                Ok(Ok(stream)) => return Ok(stream),
+                Ok(Err(err)) => last_err = Some(err),
+                Err(_) => {
+                    last_err = Some(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("connecting to {addr} timed out"),
+                    ));
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("'{}' did not resolve to any address", self.addr),
+            )
+        }))
+    }
+
+    /// Connects to `addr` and, with TLS set up, completes the TLS handshake.
+    async fn connect_to(&self, addr: SocketAddr) -> io::Result<ReceiverStream> {
+        let stream = TcpStream::connect(addr).await?;
+        let Some(config) = &self.tls else {
+            return Ok(Box::new(stream));
+        };
+        let name = ServerName::try_from(SERVER_NAME)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let stream = TlsConnector::from(Arc::clone(config))
+            .connect(name, stream)
+            .await?;
+        Ok(Box::new(stream))
+    }
+}
+
+fn interrupted() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "server stopping")
+}
+
+fn emit_status(app: &AppHandle, status: ReceiverStatus) {
+    if let Err(err) = app.emit("receiver-status", status) {
+        warn!("Error emitting receiver-status: {err}");
+    }
+}
@@ -0,0 +1,511 @@
+#![warn(clippy::all,
+clippy::pedantic,
+clippy::perf,
+clippy::nursery,
+// clippy::cargo,
+clippy::unwrap_used,
+clippy::expect_used)]
+
+//! Just enough EBML parsing to follow the `MediaRecorder` byte stream: where
+//! the initialization segment ends and where each Cluster starts.
+//!
+//! Segment and Cluster are descended into (the browser writes both with an
+//! unknown size), everything else is skipped by its size.
+
+/// EBML header, first element of every stream.
+pub const EBML_ID: u32 = 0x1A45_DFA3;
+pub const SEGMENT_ID: u32 = 0x1853_8067;
+pub const CLUSTER_ID: u32 = 0x1F43_B675;
+
+/// Past this the "init segment" is clearly not one; stop caching it.
+const MAX_INIT_SEGMENT: usize = 1024 * 1024;
+/// Past this a Cluster isn't kept for replay; a reconnect waits for the next one.
+const MAX_REPLAY_CLUSTER: usize = 8 * 1024 * 1024;
+
+/// Part of the stream, cut so that every Cluster starts a fragment of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    pub bytes: Vec<u8>,
+    /// `bytes` begins with a Cluster header, so a decoder that has the init
+    /// segment can start here
+    pub cluster_start: bool,
+    /// `bytes` begins with an EBML header: the recorder started a new stream,
+    /// whose init segment follows
+    pub stream_start: bool,
+}
+
+/// Incremental scanner over a recorder byte stream fed in arbitrary chunks.
+#[derive(Default)]
+pub struct WebmScanner {
+    /// Stream offset of the next byte fed
+    pos: u64,
+    /// Element header (ID + size) read so far, and where it started. Held
+    /// back from the output until we know whether it starts a Cluster.
+    header: Vec<u8>,
+    header_start: u64,
+    /// Payload bytes of the current element still to skip
+    skip: u64,
+    /// Met something we can't size (corrupt data, unknown-size element we
+    /// don't descend into): look for the next Cluster or EBML header ID byte
+    /// by byte
+    lost: bool,
+    lost_window: u32,
+    /// Last bytes of the previous chunk while lost, held back from the output
+    /// in case they start an ID the next chunk completes
+    lost_tail: Vec<u8>,
+
+    /// EBML header + Segment start + Info/Tracks..., everything before the first Cluster
+    init: Vec<u8>,
+    collecting_init: bool,
+    init_ready: bool,
+}
+
+/// Fragments produced by one [`WebmScanner::feed`] call.
+#[derive(Default)]
+struct Output {
+    fragments: Vec<Fragment>,
+    current: Vec<u8>,
+    cluster_start: bool,
+    stream_start: bool,
+}
+
+impl Output {
+    fn start_cluster(&mut self) {
+        self.finish();
+        self.cluster_start = true;
+    }
+
+    fn start_stream(&mut self) {
+        self.finish();
+        self.stream_start = true;
+    }
+
+    fn finish(&mut self) {
+        if !self.current.is_empty() {
+            self.fragments.push(Fragment {
+                bytes: std::mem::take(&mut self.current),
+                cluster_start: self.cluster_start,
+                stream_start: self.stream_start,
+            });
+        }
+        self.cluster_start = false;
+        self.stream_start = false;
+    }
+}
+
+impl WebmScanner {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            collecting_init: true,
+            ..Self::default()
+        }
+    }
+
+    /// Initialization segment of the current stream, once its first Cluster has been seen.
+    #[must_use]
+    pub fn init_segment(&self) -> Option<&[u8]> {
+        self.init_ready.then_some(self.init.as_slice())
+    }
+
+    /// Consumes the next chunk of the stream. The bytes come back (in order,
+    /// minus an incomplete element header or ID held until the next call) as
+    /// fragments cut at Cluster and EBML header starts.
+    pub fn feed(&mut self, data: &[u8]) -> Vec<Fragment> {
+        // Scanned again from the start of the window, which was reset when they were held
+        let tail = std::mem::take(&mut self.lost_tail);
+        let joined;
+        let data = if tail.is_empty() {
+            data
+        } else {
+            joined = [tail.as_slice(), data].concat();
+            joined.as_slice()
+        };
+        let data_start = self.pos - tail.len() as u64;
+        self.pos = data_start + data.len() as u64;
+        let mut out = Output::default();
+        // Bytes of `data` before this index are in `out`
+        let mut emitted = 0;
+
+        let mut i = 0;
+        while i < data.len() {
+            if self.lost {
+                self.lost_window = (self.lost_window << 8) | u32::from(data[i]);
+                i += 1;
+                if matches!(self.lost_window, CLUSTER_ID | EBML_ID) {
+                    // Resume parsing at the header, its size comes next
+                    self.lost = false;
+                    self.header = self.lost_window.to_be_bytes().to_vec();
+                    self.header_start = data_start + (i - 4) as u64;
+                }
+                continue;
+            }
+
+            if self.skip > 0 {
+                let n = usize::try_from(self.skip)
+                    .unwrap_or(usize::MAX)
+                    .min(data.len() - i);
+                i += n;
+                self.skip -= n as u64;
+                continue;
+            }
+
+            if self.header.is_empty() {
+                self.header_start = data_start + i as u64;
+            }
+            self.header.push(data[i]);
+            i += 1;
+
+            let Some(parsed) = parse_header(&self.header) else {
+                continue;
+            };
+            // Flush what precedes the header; the header itself may open a new fragment
+            let header_from = self.header_in(data_start).max(emitted);
+            self.emit(&mut out, &data[emitted..header_from]);
+            let header = std::mem::take(&mut self.header);
+            emitted = i;
+
+            let Ok((id, size)) = parsed else {
+                self.emit(&mut out, &header);
+                self.lost = true;
+                self.lost_window = 0;
+                continue;
+            };
+            match id {
+                SEGMENT_ID => {}
+                CLUSTER_ID => {
+                    if self.collecting_init {
+                        self.collecting_init = false;
+                        self.init_ready = true;
+                    }
+                    out.start_cluster();
+                }
+                _ => {
+                    if id == EBML_ID {
+                        // A new stream (the recorder restarted): forget the old init segment
+                        self.init.clear();
+                        self.init_ready = false;
+                        self.collecting_init = true;
+                        out.start_stream();
+                    }
+                    if let Some(size) = size {
+                        self.skip = size;
+                    } else {
+                        self.lost = true;
+                        self.lost_window = 0;
+                    }
+                }
+            }
+            self.emit(&mut out, &header);
+        }
+
+        let end = if self.lost {
+            // The window never ends on a match here, so at most 3 bytes of one
+            let end = data.len().saturating_sub(3).max(emitted);
+            self.lost_tail = data[end..].to_vec();
+            self.lost_window = 0;
+            end
+        } else if self.header.is_empty() {
+            data.len()
+        } else {
+            self.header_in(data_start).max(emitted)
+        };
+        self.emit(&mut out, &data[emitted..end]);
+        out.finish();
+        out.fragments
+    }
+
+    /// Where the pending header starts in the chunk that began at `data_start`
+    /// (0 if it began in an earlier one).
+    fn header_in(&self, data_start: u64) -> usize {
+        usize::try_from(self.header_start.saturating_sub(data_start)).unwrap_or(usize::MAX)
+    }
+
+    fn emit(&mut self, out: &mut Output, bytes: &[u8]) {
+        out.current.extend_from_slice(bytes);
+        if self.collecting_init {
+            self.init.extend_from_slice(bytes);
+            if self.init.len() > MAX_INIT_SEGMENT {
+                self.init = Vec::new();
+                self.collecting_init = false;
+            }
+        }
+    }
+}
+
+/// The part of the current Cluster already forwarded, so a fresh decoder can
+/// get it again after a reconnect instead of waiting for the next Cluster.
+#[derive(Default)]
+pub struct ClusterReplay {
+    bytes: Vec<u8>,
+    /// `bytes` starts at a Cluster header and nothing since was left out
+    whole: bool,
+}
+
+impl ClusterReplay {
+    /// Takes note of `fragment`, just forwarded.
+    pub fn record(&mut self, fragment: &Fragment) {
+        if fragment.cluster_start {
+            self.bytes.clear();
+            self.whole = true;
+        } else if fragment.stream_start {
+            self.bytes.clear();
+            self.whole = false;
+        }
+        if !self.whole {
+            return;
+        }
+        if self.bytes.len() + fragment.bytes.len() > MAX_REPLAY_CLUSTER {
+            self.bytes = Vec::new();
+            self.whole = false;
+            return;
+        }
+        self.bytes.extend_from_slice(&fragment.bytes);
+    }
+
+    /// What was forwarded of the current Cluster, from its header on. `None`
+    /// when there is no such Cluster or it wasn't kept.
+    #[must_use]
+    pub fn cluster(&self) -> Option<&[u8]> {
+        self.whole.then_some(self.bytes.as_slice())
+    }
+}
+
+/// Parses an element ID and data size from the start of `header`.
+///
+/// `None` if more bytes are needed, `Some(Err(()))` if this isn't valid EBML.
+/// The size is `None` when it is "unknown" (all value bits set).
+fn parse_header(header: &[u8]) -> Option<Result<(u32, Option<u64>), ()>> {
+    let first = *header.first()?;
+    let id_len = first.leading_zeros() as usize + 1;
+    if id_len > 4 {
+        return Some(Err(()));
+    }
+    let size_first = *header.get(id_len)?;
+    let size_marker = size_first.leading_zeros();
+    let size_len = size_marker as usize + 1;
+    if size_len > 8 {
+        return Some(Err(()));
+    }
+    if header.len() < id_len + size_len {
+        return None;
+    }
+
+    // IDs keep their length marker bits, sizes don't
+    let id = header[..id_len]
+        .iter()
+        .fold(0u32, |acc, &b| (acc << 8) | u32::from(b));
+    // An 8-byte size has no value bits in its first byte
+    let mask = u8::MAX.checked_shr(size_marker + 1).unwrap_or(0);
+    let value = header[id_len + 1..id_len + size_len]
+        .iter()
+        .fold(u64::from(size_first & mask), |acc, &b| {
+            (acc << 8) | u64::from(b)
+        });
+    let unknown = value == (1u64 << (7 * size_len)) - 1;
+
+    Some(Ok((id, (!unknown).then_some(value))))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    const EBML_HEADER: [u8; 9] = [0x1A, 0x45, 0xDF, 0xA3, 0x84, 0x42, 0x86, 0x81, 0x01];
+    /// Unknown size in its 8-byte form, as `MediaRecorder` writes it
+    const SEGMENT_HEADER: [u8; 12] = [
+        0x18, 0x53, 0x80, 0x67, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    ];
+    /// Info and Tracks, their contents made up
+    const TRACKS: [u8; 12] = [
+        0x15, 0x49, 0xA9, 0x66, 0x83, 1, 2, 3, 0x16, 0x54, 0xAE, 0x6B,
+    ];
+
+    fn init_segment(track: u8) -> Vec<u8> {
+        [
+            &EBML_HEADER[..],
+            &SEGMENT_HEADER,
+            &TRACKS,
+            &[0x82, track, track],
+        ]
+        .concat()
+    }
+
+    /// An unknown-size Cluster with a Timecode and a `SimpleBlock`.
+    fn cluster(timecode: u8) -> Vec<u8> {
+        vec![
+            0x1F, 0x43, 0xB6, 0x75, 0xFF, 0xE7, 0x81, timecode, 0xA3, 0x84, 0x81, 0, 0, timecode,
+        ]
+    }
+
+    fn stream(track: u8) -> Vec<u8> {
+        [init_segment(track), cluster(0), cluster(1)].concat()
+    }
+
+    fn feed_chunks<'a>(
+        scanner: &mut WebmScanner,
+        chunks: impl IntoIterator<Item = &'a [u8]>,
+    ) -> Vec<Fragment> {
+        let mut fragments: Vec<Fragment> = Vec::new();
+        for chunk in chunks {
+            for fragment in scanner.feed(chunk) {
+                // A chunk boundary alone doesn't start a fragment
+                match fragments.last_mut() {
+                    Some(last) if !fragment.cluster_start && !fragment.stream_start => {
+                        last.bytes.extend_from_slice(&fragment.bytes);
+                    }
+                    _ => fragments.push(fragment),
+                }
+            }
+        }
+        fragments
+    }
+
+    fn feed_bytewise(scanner: &mut WebmScanner, data: &[u8]) -> Vec<Fragment> {
+        feed_chunks(scanner, data.chunks(1))
+    }
+
+    fn joined(fragments: &[Fragment]) -> Vec<u8> {
+        fragments.iter().flat_map(|f| f.bytes.clone()).collect()
+    }
+
+    fn flags(fragments: &[Fragment]) -> Vec<(bool, bool)> {
+        fragments
+            .iter()
+            .map(|f| (f.stream_start, f.cluster_start))
+            .collect()
+    }
+
+    #[test]
+    fn fragments_dont_depend_on_chunking() {
+        let data = [stream(1), stream(2)].concat();
+        let whole = feed_chunks(&mut WebmScanner::new(), [data.as_slice()]);
+        assert_eq!(feed_bytewise(&mut WebmScanner::new(), &data), whole);
+        for size in [2, 3, 5, 7] {
+            assert_eq!(
+                feed_chunks(&mut WebmScanner::new(), data.chunks(size)),
+                whole,
+                "chunks of {size}"
+            );
+        }
+        assert_eq!(joined(&whole), data);
+    }
+
+    #[test]
+    fn init_segment_is_everything_before_the_first_cluster() {
+        let data = stream(1);
+        let init_len = init_segment(1).len();
+        let mut scanner = WebmScanner::new();
+        scanner.feed(&data[..init_len]);
+        assert_eq!(scanner.init_segment(), None);
+        scanner.feed(&data[init_len..]);
+        assert_eq!(scanner.init_segment(), Some(&init_segment(1)[..]));
+    }
+
+    #[test]
+    fn fragments_start_at_streams_and_clusters() {
+        let data = [stream(1), stream(2)].concat();
+        let mut scanner = WebmScanner::new();
+        let fragments = feed_chunks(&mut scanner, [data.as_slice()]);
+        assert_eq!(
+            flags(&fragments),
+            [
+                (true, false),
+                (false, true),
+                (false, true),
+                (true, false),
+                (false, true),
+                (false, true),
+            ]
+        );
+        assert_eq!(fragments[0].bytes, init_segment(1));
+        assert_eq!(fragments[1].bytes, cluster(0));
+        assert_eq!(fragments[3].bytes, init_segment(2));
+        // The restarted stream's init segment replaced the first one
+        assert_eq!(scanner.init_segment(), Some(&init_segment(2)[..]));
+    }
+
+    #[test]
+    fn eight_byte_sizes_are_parsed() {
+        assert_eq!(parse_header(&SEGMENT_HEADER), Some(Ok((SEGMENT_ID, None))));
+        let known = [0x18, 0x53, 0x80, 0x67, 0x01, 0, 0, 0, 0, 0, 0x01, 0x02];
+        assert_eq!(parse_header(&known), Some(Ok((SEGMENT_ID, Some(0x0102)))));
+        assert_eq!(parse_header(&known[..11]), None);
+    }
+
+    #[test]
+    fn resyncs_at_the_next_cluster_after_garbage() {
+        // 0x00 can't start an ID, leaving the scanner lost
+        let garbage = [0x00, 0x1F, 0x43, 0x12, 0x00];
+        let data = [stream(1), garbage.to_vec(), cluster(2), cluster(3)].concat();
+        let cluster_at = stream(1).len() + garbage.len();
+
+        // Split inside the Cluster ID, at every offset
+        for split in cluster_at..cluster_at + 4 {
+            let fragments = feed_chunks(&mut WebmScanner::new(), [&data[..split], &data[split..]]);
+            assert_eq!(joined(&fragments), data, "split at {split}");
+            let resynced = &fragments[fragments.len() - 2..];
+            assert_eq!(flags(resynced), [(false, true), (false, true)]);
+            assert_eq!(resynced[0].bytes, cluster(2), "split at {split}");
+        }
+        let bytewise = feed_bytewise(&mut WebmScanner::new(), &data);
+        assert_eq!(
+            bytewise,
+            feed_chunks(&mut WebmScanner::new(), [data.as_slice()])
+        );
+    }
+
+    #[test]
+    fn reconnecting_mid_cluster_resumes_at_its_start() {
+        let data = stream(1);
+        // Two clusters in, the second cut in half by the phone's chunking
+        let split = init_segment(1).len() + cluster(0).len() + 6;
+        let mut scanner = WebmScanner::new();
+        let mut replay = ClusterReplay::default();
+        for fragment in scanner.feed(&data[..split]) {
+            replay.record(&fragment);
+        }
+        let next = scanner.feed(&data[split..]);
+        assert!(!next[0].cluster_start);
+
+        // What a fresh receiver connection gets: init segment, replay, the failed fragment
+        let init = scanner.init_segment().unwrap();
+        let resumed = [init, replay.cluster().unwrap(), &next[0].bytes].concat();
+        assert_eq!(
+            parse_header(&resumed[init.len()..]).unwrap().unwrap().0,
+            CLUSTER_ID
+        );
+        assert_eq!(resumed, [init_segment(1), cluster(1)].concat());
+    }
+
+    #[test]
+    fn no_replay_after_a_new_stream_starts() {
+        let mut scanner = WebmScanner::new();
+        let mut replay = ClusterReplay::default();
+        for fragment in feed_chunks(&mut scanner, [stream(1).as_slice()]) {
+            replay.record(&fragment);
+        }
+        assert_eq!(replay.cluster(), Some(&cluster(1)[..]));
+        for fragment in scanner.feed(&init_segment(2)) {
+            replay.record(&fragment);
+        }
+        assert_eq!(replay.cluster(), None);
+    }
+
+    #[test]
+    fn resyncs_at_a_new_stream_after_garbage() {
+        let data = [stream(1), vec![0x00, 0x1A, 0x45], stream(2)].concat();
+        for fragments in [
+            feed_chunks(&mut WebmScanner::new(), [data.as_slice()]),
+            feed_bytewise(&mut WebmScanner::new(), &data),
+        ] {
+            assert_eq!(joined(&fragments), data);
+            let restart = fragments.iter().rposition(|f| f.stream_start).unwrap();
+            assert_eq!(fragments[restart].bytes, init_segment(2));
+        }
+        let mut scanner = WebmScanner::new();
+        feed_bytewise(&mut scanner, &data);
+        assert_eq!(scanner.init_segment(), Some(&init_segment(2)[..]));
+    }
+}
@@ -5,9 +5,112 @@ import { listen } from "@tauri-apps/api/event";
 import "./App.css";
 
 interface RustLog {
+  timestamp_ms: number;
+  level: string;
+  target: string;
+  conn_id: number | null;
   log_message: string;
 }
 
+function formatLog(log: RustLog): string {
+  const time = new Date(log.timestamp_ms).toLocaleTimeString();
+  const conn = log.conn_id !== null ? ` [conn ${log.conn_id}]` : "";
+  return `${time} [BACKEND] ${log.level}${conn} ${log.log_message}`;
+}
+
+interface ServerAddress {
+  address: string;
+  port: number;
+}
+
+// Result of `parse_pairing`, named like `start_server`'s options
+interface PairingSettings {
+  tcpAddr: string;
+  psk: string | null;
+  receiverFingerprint: string | null;
+}
+
+// A receiver found by `discover_receivers` or `listen_for_beacons`
+interface ReceiverInfo {
+  name: string;
+  addresses: string[];
+  port: number;
+  version: number;
+  device_path: string;
+  width: number;
+  height: number;
+  tls: boolean;
+  // Ping round trip, only measured for receivers heard through beacons
+  latency_ms?: number | null;
+}
+
+function receiverAddr(receiver: ReceiverInfo): string {
+  const ip = receiver.addresses[0];
+  return `${ip.includes(":") ? `[${ip}]` : ip}:${receiver.port}`;
+}
+
+interface ServerError {
+  message: string;
+}
+
+type ReceiverStatus =
+  | { state: "connecting"; conn_id: number; attempt: number }
+  | { state: "connected"; conn_id: number }
+  | { state: "retrying"; conn_id: number; attempt: number; retry_in_secs: number; error: string }
+  | { state: "failed"; conn_id: number; error: string };
+
+function describeReceiverStatus(status: ReceiverStatus): string {
+  switch (status.state) {
+    case "connecting":
+      return `Connecting to the computer (attempt ${status.attempt})...`;
+    case "connected":
+      return "Connected to the computer";
+    case "retrying":
+      return `Computer unreachable (${status.error}), retrying in ${status.retry_in_secs.toFixed(1)} s`;
+    case "failed":
+      return `Couldn't reach the computer: ${status.error}`;
+  }
+}
+
+interface BridgeStats {
+  conn_id: number;
+  bytes_sent: number;
+  clusters_sent: number;
+  bytes_dropped: number;
+  clusters_dropped: number;
+  queue_latency_ms: number;
+}
+
+function describeBridgeStats(stats: BridgeStats): string {
+  const sent = `${(stats.bytes_sent / 1e6).toFixed(1)} MB sent, ${stats.queue_latency_ms} ms behind`;
+  if (stats.clusters_dropped === 0) {
+    return sent;
+  }
+  return `${sent}, dropped ${stats.clusters_dropped} cluster(s) (${(stats.bytes_dropped / 1e6).toFixed(1)} MB) to keep up`;
+}
+
+type ServerStatus =
+  | { state: "stopped" }
+  | { state: "running"; address: string; port: number; clients: number };
+
+// Where the Rust WebSocket bridge ended up listening (reported by the backend)
+let bridgeUrl: string | null = null;
+
+function nextMessage(websocket: WebSocket): Promise<MessageEvent> {
+  return new Promise((resolve) => {
+    websocket.addEventListener("message", resolve, { once: true });
+  });
+}
+
+// Proof of knowing the pre-shared key: HMAC-SHA256(key, "camstream auth v1" + nonce), hex-encoded
+async function answerChallenge(psk: string, nonce: string): Promise<string> {
+  const encoder = new TextEncoder();
+  const key = await crypto.subtle.importKey(
+    "raw", encoder.encode(psk), { name: "HMAC", hash: "SHA-256" }, false, ["sign"]);
+  const mac = await crypto.subtle.sign("HMAC", key, encoder.encode("camstream auth v1" + nonce));
+  return Array.from(new Uint8Array(mac), (b) => b.toString(16).padStart(2, "0")).join("");
+}
+
 class MediaRecorderStreamer {
   video: HTMLVideoElement;
   mediaStream: MediaStream | null;
@@ -38,7 +141,7 @@ class MediaRecorderStreamer {
         }
     }
 
-    async startStreaming(serverUrl = 'ws://localhost:8080') {
+    async startStreaming(serverUrl = 'ws://localhost:8080', psk = "") {
         try {
             // Start camera
             await this.startCamera();
@@ -52,6 +155,28 @@ class MediaRecorderStreamer {
             });
 
             console.log("Started ws");
+
+            // With a key set, the bridge challenges us before anything else
+            if (psk) {
+                const challenge = JSON.parse((await nextMessage(this.websocket)).data);
+                this.websocket.send(JSON.stringify({ mac: await answerChallenge(psk, challenge.nonce) }));
+            }
+
+            // Announce the stream; the bridge answers with the receiver's ack
+            this.websocket.onmessage = (event) => {
+                console.log("Receiver answered:", event.data);
+            };
+            const settings = this.mediaStream!.getVideoTracks()[0].getSettings();
+            this.websocket.send(JSON.stringify({
+                device_name: navigator.userAgent,
+                codec: 'h264',
+                container: 'webm',
+                width: settings.width ?? 0,
+                height: settings.height ?? 0,
+                fps: settings.frameRate ?? 0,
+                // The WebView already hands us upright frames
+                orientation: 0,
+            }));
             
             // Setup MediaRecorder
             this.mediaRecorder = new MediaRecorder(this.mediaStream!, {
@@ -98,26 +223,113 @@ class MediaRecorderStreamer {
 function App() {
   // const [responseMsg, setResponseMsg] = useState("");
   const [addr, setAddr] = useState("");
+  const [psk, setPsk] = useState("");
+  const [fingerprint, setFingerprint] = useState("");
   const [logString, setLogString] = useState("");
+  const [serverStatus, setServerStatus] = useState<ServerStatus>({ state: "stopped" });
+  const [receiverStatus, setReceiverStatus] = useState("");
+  const [bridgeStats, setBridgeStats] = useState("");
+  const [receivers, setReceivers] = useState<ReceiverInfo[]>([]);
 
   const streamer = new MediaRecorderStreamer();
 
   const start_streaming = useCallback(async () => {
     setLogString(logString + "\n" + "[FRONTEND] calling start stream");
-    setLogString(logString + "\n" + await invoke('start_server', { device: "This shouldn't be used", tcpAddr: addr }));
+    try {
+      const bound = await invoke<ServerAddress>('start_server', {
+        options: {
+          tcpAddr: addr,
+          psk: psk || null,
+          receiverFingerprint: fingerprint || null,
+        },
+      });
+      setLogString(logString + "\n" + `[FRONTEND] Server listening on ${bound.address}:${bound.port}`);
+    } catch (error) {
+      // Bind failures (port in use, bad address...) come back as the command's error
+      setLogString(logString + "\n" + `[FRONTEND] Couldn't start server: ${error}`);
+    }
     setLogString(logString + "\n" + "[FRONTEND] Exiting function call");
-  }, [addr]);
+  }, [addr, psk, fingerprint]);
+
+  // Fills the settings in from the code the receiver prints at startup
+  const pair = useCallback(async (code: string) => {
+    try {
+      const settings = await invoke<PairingSettings>('parse_pairing', { code });
+      setAddr(settings.tcpAddr);
+      setPsk(settings.psk ?? "");
+      setFingerprint(settings.receiverFingerprint ?? "");
+      setLogString(logString + "\n" + `[FRONTEND] Paired with ${settings.tcpAddr}`);
+    } catch (error) {
+      setLogString(logString + "\n" + `[FRONTEND] Couldn't pair: ${error}`);
+    }
+  }, [logString]);
+
+  const discover = useCallback(async () => {
+    try {
+      const found = await invoke<ReceiverInfo[]>('discover_receivers');
+      setReceivers(found.filter((receiver) => receiver.addresses.length > 0));
+      setLogString(logString + "\n" + `[FRONTEND] Found ${found.length} receiver(s)`);
+    } catch (error) {
+      setLogString(logString + "\n" + `[FRONTEND] Couldn't look for receivers: ${error}`);
+    }
+  }, [logString]);
+
+  // For networks that block mDNS: listens for the receivers' UDP broadcasts
+  const listenForBeacons = useCallback(async () => {
+    try {
+      const found = await invoke<ReceiverInfo[]>('listen_for_beacons', { psk: psk || null });
+      setReceivers(found);
+      setLogString(logString + "\n" + `[FRONTEND] Heard ${found.length} receiver(s)`);
+    } catch (error) {
+      setLogString(logString + "\n" + `[FRONTEND] Couldn't listen for receivers: ${error}`);
+    }
+  }, [logString, psk]);
+
+  const stop_streaming = useCallback(async () => {
+    streamer.stopStreaming();
+    await invoke('stop_server');
+    setServerStatus(await invoke<ServerStatus>('server_status'));
+  }, []);
+
+  // Backlog from before the UI was listening
+  useEffect(() => {
+    invoke<RustLog[]>('get_logs', { limit: 200 }).then((logs) => {
+      setLogString(logs.map(formatLog).join("\n"));
+    });
+  }, []);
 
   listen<RustLog>("rust-log", useCallback((event) => {
-    setLogString(logString + "\n" + event.payload.log_message)
+    setLogString(logString + "\n" + formatLog(event.payload))
   }, [logString, setLogString, addr]));
 
 
-  listen("server-running", useCallback(async (_event) => {
-    setLogString(logString + "\n" + "[FRONTEND] Starting streaming");
-    await streamer.startStreaming();
+  listen<ReceiverStatus>("receiver-status", useCallback((event) => {
+    setReceiverStatus(describeReceiverStatus(event.payload));
+  }, [setReceiverStatus]));
+
+  listen<BridgeStats>("bridge-stats", useCallback((event) => {
+    setBridgeStats(describeBridgeStats(event.payload));
+  }, [setBridgeStats]));
+
+  listen("server-stopped", useCallback((_event) => {
+    bridgeUrl = null;
+    setServerStatus({ state: "stopped" });
+    setLogString(logString + "\n" + "[BACKEND] Server stopped");
+  }, [logString, setLogString]));
+
+  listen<ServerError>("server-error", useCallback((event) => {
+    setLogString(logString + "\n" + "[BACKEND] " + event.payload.message);
+  }, [logString, setLogString]));
+
+  // Only emitted once the bridge is bound, with the address it is listening on
+  listen<ServerAddress>("server-running", useCallback(async (event) => {
+    const { address, port } = event.payload;
+    bridgeUrl = `ws://${address.includes(":") ? `[${address}]` : address}:${port}`;
+    setLogString(logString + "\n" + `[FRONTEND] Starting streaming to ${bridgeUrl}`);
+    await streamer.startStreaming(bridgeUrl ?? undefined, psk);
     setLogString(logString + "\n" + "[FRONTEND] Started");
-  }, [logString, setLogString, addr]));
+    setServerStatus(await invoke<ServerStatus>('server_status'));
+  }, [logString, setLogString, addr, psk]));
 
   useEffect(() => {
     streamer.networkAddr = addr;
@@ -130,6 +342,34 @@ function App() {
         autoPlay
         muted></video>
 
+      <form
+        className="row"
+        onSubmit={ async (e) => {
+          e.preventDefault();
+          const code = new FormData(e.currentTarget).get("pairing-code");
+          await pair(String(code ?? ""));
+        }}
+      >
+        <input
+          id="pairing-input"
+          name="pairing-code"
+          placeholder="Pairing code (camstream:...)"
+        />
+        <button type="submit">Pair</button>
+        <button type="button" onClick={discover}>Find receivers</button>
+        <button type="button" onClick={listenForBeacons}>Find by broadcast</button>
+      </form>
+      {receivers.map((receiver) => (
+        <button
+          key={receiver.name}
+          type="button"
+          onClick={() => setAddr(receiverAddr(receiver))}
+        >
+          {receiver.name} ({receiverAddr(receiver)}, {receiver.width}x{receiver.height}
+          {receiver.tls ? ", TLS" : ""}
+          {receiver.latency_ms != null ? `, ${receiver.latency_ms.toFixed(1)} ms` : ""})
+        </button>
+      ))}
       <form
         className="row"
         onSubmit={ async (e) => {
@@ -139,12 +379,32 @@ function App() {
       >
         <input
           id="greet-input"
+          value={addr}
           onChange={(e) => setAddr(e.currentTarget.value)}
           placeholder="Enter a addr..."
         />
+        <input
+          id="psk-input"
+          type="password"
+          value={psk}
+          onChange={(e) => setPsk(e.currentTarget.value)}
+          placeholder="Pairing key (optional)"
+        />
+        <input
+          id="fingerprint-input"
+          value={fingerprint}
+          onChange={(e) => setFingerprint(e.currentTarget.value)}
+          placeholder="Receiver TLS fingerprint (optional)"
+        />
         <button type="submit">Start Stream</button>
+        <button type="button" onClick={stop_streaming}>Stop Stream</button>
       </form>
       <p>Current Addr: {addr}</p>
+      <p>Server: {serverStatus.state === "running"
+        ? `running on ${serverStatus.address}:${serverStatus.port} (${serverStatus.clients} client(s))`
+        : serverStatus.state}</p>
+      {receiverStatus && <p>Computer: {receiverStatus}</p>}
+      {bridgeStats && <p>Link: {bridgeStats}</p>}
       <p>Rust Logs:</p>
       <div style={{whiteSpace: 'pre-wrap'}}>{logString}</div>
     </main>
@@ -0,0 +1,103 @@
+//! Pre-shared-key challenge/response.
+//!
+//! The verifying side sends a [`Challenge`] with a fresh random nonce; the
+//! other side proves it knows the key by answering with
+//! `HMAC-SHA256(key, "camstream auth v1" || nonce)`, the nonce taken as the
+//! hex string it is sent as. The key itself never crosses the wire.
+//!
+//! Only the side answering the challenge is authenticated: the receiver learns
+//! the sender knows the key, not the other way round. A sender that must be
+//! sure it reached the right receiver relies on TLS and the receiver's pinned
+//! [`Fingerprint`](crate::Fingerprint) for that.
+
+use std::fmt;
+use std::io;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::message::{AuthResponse, Challenge};
+
+pub const NONCE_LEN: usize = 32;
+/// Keeps these MACs from being valid for anything else keyed the same way.
+const CONTEXT: &[u8] = b"camstream auth v1";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Secret shared by the phone app and the receiver when they are paired.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PreSharedKey(Vec<u8>);
+
+impl PreSharedKey {
+    /// # Errors
+    /// If `key` is empty, which would authenticate anyone.
+    pub fn new(key: impl Into<Vec<u8>>) -> Result<Self, io::Error> {
+        let key = key.into();
+        if key.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the pre-shared key is empty",
+            ));
+        }
+        Ok(Self(key))
+    }
+
+    /// The answer to `challenge` that proves knowledge of this key.
+    #[must_use]
+    pub fn respond(&self, challenge: &Challenge) -> AuthResponse {
+        AuthResponse {
+            mac: hex::encode(self.mac(CONTEXT, challenge.nonce.as_bytes())),
+        }
+    }
+
+    /// Whether `response` answers `challenge` with this key.
+    #[must_use]
+    pub fn verify(&self, challenge: &Challenge, response: &AuthResponse) -> bool {
+        self.verify_mac(CONTEXT, challenge.nonce.as_bytes(), &response.mac)
+    }
+
+    /// `HMAC-SHA256(key, context || message)`, `context` keeping MACs made
+    /// for one purpose from being valid for another.
+    pub(crate) fn mac(&self, context: &[u8], message: &[u8]) -> [u8; 32] {
+        self.hmac(context, message).finalize().into_bytes().into()
+    }
+
+    /// Whether the hex `mac` is [`mac`](Self::mac) of `context` and `message`.
+    pub(crate) fn verify_mac(&self, context: &[u8], message: &[u8], mac: &str) -> bool {
+        let Ok(mac) = hex::decode(mac) else {
+            return false;
+        };
+        // Constant time, so timing doesn't leak how much of a guess was right
+        self.hmac(context, message).verify_slice(&mac).is_ok()
+    }
+
+    fn hmac(&self, context: &[u8], message: &[u8]) -> HmacSha256 {
+        let Ok(mut hmac) = HmacSha256::new_from_slice(&self.0) else {
+            unreachable!("HMAC takes keys of any length");
+        };
+        hmac.update(context);
+        hmac.update(message);
+        hmac
+    }
+}
+
+/// Never prints the key.
+impl fmt::Debug for PreSharedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PreSharedKey(..)")
+    }
+}
+
+impl Challenge {
+    /// A challenge with a fresh random nonce.
+    ///
+    /// # Errors
+    /// If the OS random number generator fails.
+    pub fn new() -> io::Result<Self> {
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::fill(&mut nonce)?;
+        Ok(Self {
+            nonce: hex::encode(nonce),
+        })
+    }
+}
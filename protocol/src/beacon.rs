@@ -0,0 +1,135 @@
+//! UDP broadcast discovery, for networks that drop the multicast mDNS needs.
+//!
+//! A receiver broadcasts a [`Beacon::Announce`] every [`BEACON_INTERVAL`] to
+//! [`DEFAULT_BEACON_PORT`]. A phone that hears one can time the round trip by
+//! sending a [`Beacon::Ping`] back to the address the announcement came from,
+//! which the receiver answers with a [`Beacon::Pong`] carrying the same nonce.
+//!
+//! A datagram is `{"body": <message>, "mac": <hex>}`. With a pre-shared key the
+//! MAC is `HMAC-SHA256(key, "camstream beacon v1" || body)` over the exact
+//! bytes of the body, and every message is signed: a phone paired with a
+//! receiver only lists that one, nobody else can answer its pings, and the
+//! receiver doesn't send pongs to whoever asks. Without a key there is no MAC.
+//!
+//! Signed announcements also carry the time they were sent and are refused
+//! once [`MAX_ANNOUNCE_AGE`] old, so a captured one can't be replayed later to
+//! list a receiver that's gone. Pings and pongs are tied together by the nonce.
+
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+use crate::{PreSharedKey, ReceiverInfo};
+
+pub const DEFAULT_BEACON_PORT: u16 = 47_810;
+/// How often receivers announce themselves.
+pub const BEACON_INTERVAL: Duration = Duration::from_secs(1);
+/// Larger datagrams aren't beacons.
+pub const MAX_BEACON_LEN: usize = 4096;
+/// Signed announcements sent longer ago than this, or as far in the future
+/// (the two clocks only roughly agree), are refused.
+pub const MAX_ANNOUNCE_AGE: Duration = Duration::from_secs(30);
+/// Keeps these MACs from being valid for anything else keyed the same way.
+const CONTEXT: &[u8] = b"camstream beacon v1";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Beacon {
+    /// A receiver saying it's there. Its addresses may be left empty: the
+    /// datagram's source address is one that works.
+    Announce {
+        receiver: ReceiverInfo,
+        /// When it was sent, in milliseconds since the Unix epoch
+        #[serde(default)]
+        sent_at_ms: u64,
+    },
+    /// Sent by a phone to a receiver it heard, `nonce` being fresh random hex.
+    /// Answered once per nonce, so a captured ping can't be replayed to aim
+    /// pongs at someone else.
+    Ping { nonce: String },
+    /// The receiver's answer to a ping
+    Pong { nonce: String },
+}
+
+#[derive(Serialize, Deserialize)]
+struct Datagram<'a> {
+    #[serde(borrow)]
+    body: &'a RawValue,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mac: Option<String>,
+}
+
+impl Beacon {
+    /// An announcement of `receiver`, stamped with the current time.
+    #[must_use]
+    pub fn announce(receiver: ReceiverInfo) -> Self {
+        Self::Announce {
+            receiver,
+            sent_at_ms: unix_millis(SystemTime::now()),
+        }
+    }
+
+    /// A ping with a fresh random nonce.
+    ///
+    /// # Errors
+    /// If the OS random number generator fails.
+    pub fn ping() -> io::Result<Self> {
+        let mut nonce = [0u8; 16];
+        getrandom::fill(&mut nonce)?;
+        Ok(Self::Ping {
+            nonce: hex::encode(nonce),
+        })
+    }
+
+    /// The datagram carrying this message, signed with `key` if there is one.
+    ///
+    /// # Errors
+    /// If the message can't be serialized.
+    pub fn encode(&self, key: Option<&PreSharedKey>) -> io::Result<Vec<u8>> {
+        let body = serde_json::value::to_raw_value(self).map_err(invalid)?;
+        let mac = key.map(|key| hex::encode(key.mac(CONTEXT, body.get().as_bytes())));
+        serde_json::to_vec(&Datagram { body: &body, mac }).map_err(invalid)
+    }
+
+    /// Reads a datagram. With a `key`, it must be signed with that key, and an
+    /// announcement must be recent (see [`MAX_ANNOUNCE_AGE`]); without one,
+    /// any MAC and time are ignored.
+    ///
+    /// # Errors
+    /// `InvalidData` if it isn't a beacon, isn't signed with `key` or is a stale
+    /// announcement.
+    pub fn decode(datagram: &[u8], key: Option<&PreSharedKey>) -> io::Result<Self> {
+        let datagram: Datagram = serde_json::from_slice(datagram).map_err(invalid)?;
+        let body = datagram.body.get();
+        if let Some(key) = key {
+            let mac = datagram
+                .mac
+                .ok_or_else(|| invalid("the beacon isn't signed"))?;
+            if !key.verify_mac(CONTEXT, body.as_bytes(), &mac) {
+                return Err(invalid("the beacon is signed with another key"));
+            }
+        }
+        let beacon = serde_json::from_str(body).map_err(invalid)?;
+        if let (Some(_), Self::Announce { sent_at_ms, .. }) = (key, &beacon) {
+            let skew = unix_millis(SystemTime::now()).abs_diff(*sent_at_ms);
+            if u128::from(skew) > MAX_ANNOUNCE_AGE.as_millis() {
+                return Err(invalid(format!(
+                    "the announcement was sent {skew} ms away from now"
+                )));
+            }
+        }
+        Ok(beacon)
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| {
+        u64::try_from(since.as_millis()).unwrap_or(u64::MAX)
+    })
+}
+
+fn invalid(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
@@ -0,0 +1,66 @@
+//! What receivers tell phones on the LAN about themselves, so users pick one
+//! from a list instead of typing an address.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// A receiver found on the network.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiverInfo {
+    /// Instance name, the computer's host name unless set otherwise
+    pub name: String,
+    pub addresses: Vec<IpAddr>,
+    pub port: u16,
+    /// Protocol version the receiver speaks
+    pub version: u16,
+    /// Virtual camera the stream ends up in
+    pub device_path: String,
+    /// Output size of the virtual camera
+    pub width: u32,
+    pub height: u32,
+    /// Senders must connect over TLS (the fingerprint comes from pairing)
+    pub tls: bool,
+}
+
+impl ReceiverInfo {
+    /// The DNS-SD TXT record describing this receiver. The name, addresses and
+    /// port travel in records of their own.
+    #[must_use]
+    pub fn txt_properties(&self) -> HashMap<String, String> {
+        HashMap::from([
+            ("version".to_string(), self.version.to_string()),
+            ("device".to_string(), self.device_path.clone()),
+            ("width".to_string(), self.width.to_string()),
+            ("height".to_string(), self.height.to_string()),
+            ("tls".to_string(), u8::from(self.tls).to_string()),
+        ])
+    }
+
+    /// Reverse of [`txt_properties`](Self::txt_properties). `None` without a
+    /// protocol version, which every receiver announces; other missing
+    /// entries are left empty.
+    #[must_use]
+    pub fn from_txt_properties(
+        name: String,
+        addresses: Vec<IpAddr>,
+        port: u16,
+        txt: &HashMap<String, String>,
+    ) -> Option<Self> {
+        fn number<T: FromStr>(txt: &HashMap<String, String>, key: &str) -> Option<T> {
+            txt.get(key).and_then(|value| value.parse().ok())
+        }
+        Some(Self {
+            name,
+            addresses,
+            port,
+            version: number(txt, "version")?,
+            device_path: txt.get("device").cloned().unwrap_or_default(),
+            width: number(txt, "width").unwrap_or_default(),
+            height: number(txt, "height").unwrap_or_default(),
+            tls: txt.get("tls").is_some_and(|tls| tls == "1"),
+        })
+    }
+}
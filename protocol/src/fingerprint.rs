@@ -0,0 +1,89 @@
+//! Certificate pinning: the receiver's self-signed TLS certificate is trusted
+//! by its SHA-256 fingerprint, shown by the receiver and entered on the phone.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+/// Name the receiver's certificate is issued for. Pinning makes it irrelevant
+/// to trust, but TLS still wants one.
+pub const SERVER_NAME: &str = "camstream-receiver";
+
+/// SHA-256 of a DER-encoded certificate.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint([u8; 32]);
+
+impl Fingerprint {
+    #[must_use]
+    pub fn of(cert_der: &[u8]) -> Self {
+        Self(Sha256::digest(cert_der).into())
+    }
+
+    #[must_use]
+    pub fn matches(&self, cert_der: &[u8]) -> bool {
+        *self == Self::of(cert_der)
+    }
+}
+
+/// Upper-case hex pairs separated by colons, the way certificate tools print them.
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(":")?;
+            }
+            write!(f, "{byte:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Fingerprint({self})")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidFingerprint;
+
+impl fmt::Display for InvalidFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("expected 64 hex digits (SHA-256), optionally separated by colons or spaces")
+    }
+}
+
+impl std::error::Error for InvalidFingerprint {}
+
+/// Accepts the [`Display`](fmt::Display) form as well as bare hex in either
+/// case, so a fingerprint can be typed in however it was copied.
+impl FromStr for Fingerprint {
+    type Err = InvalidFingerprint;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits: String = s
+            .chars()
+            .filter(|c| !matches!(c, ':' | ' ' | '-'))
+            .collect();
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(digits, &mut bytes).map_err(|_| InvalidFingerprint)?;
+        Ok(Self(bytes))
+    }
+}
+
+/// As its [`Display`](fmt::Display) form.
+impl Serialize for Fingerprint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Fingerprint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
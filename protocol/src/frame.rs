@@ -0,0 +1,216 @@
+//! Length-prefixed framing: `kind: u8 | length: u32 big-endian | payload`.
+
+// The header helpers are for `tokio_io` only, which the visibility should say
+// rather than leave to this module being private
+#![allow(clippy::redundant_pub_crate)]
+
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::message::{
+    AuthResponse, Challenge, Control, ErrorMessage, Heartbeat, Hello, HelloAck, Stats,
+};
+use crate::{HEADER_LEN, MAX_FRAME_LEN};
+
+/// One unit on the wire.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    Hello(Hello),
+    HelloAck(HelloAck),
+    /// Container bytes, cut anywhere
+    Media(Vec<u8>),
+    Stats(Stats),
+    Control(Control),
+    Error(ErrorMessage),
+    Heartbeat(Heartbeat),
+    Challenge(Challenge),
+    Auth(AuthResponse),
+}
+
+impl Frame {
+    const HELLO: u8 = 1;
+    const HELLO_ACK: u8 = 2;
+    const MEDIA: u8 = 3;
+    const STATS: u8 = 4;
+    const CONTROL: u8 = 5;
+    const ERROR: u8 = 6;
+    const HEARTBEAT: u8 = 7;
+    const CHALLENGE: u8 = 8;
+    const AUTH: u8 = 9;
+
+    /// Kind byte and payload, as written after the header.
+    pub(crate) fn to_parts(&self) -> io::Result<(u8, Cow<'_, [u8]>)> {
+        let (kind, json) = match self {
+            Self::Media(bytes) => return Ok((Self::MEDIA, Cow::Borrowed(bytes))),
+            Self::Hello(hello) => (Self::HELLO, to_json(hello)?),
+            Self::HelloAck(ack) => (Self::HELLO_ACK, to_json(ack)?),
+            Self::Stats(stats) => (Self::STATS, to_json(stats)?),
+            Self::Control(control) => (Self::CONTROL, to_json(control)?),
+            Self::Error(error) => (Self::ERROR, to_json(error)?),
+            Self::Heartbeat(heartbeat) => (Self::HEARTBEAT, to_json(heartbeat)?),
+            Self::Challenge(challenge) => (Self::CHALLENGE, to_json(challenge)?),
+            Self::Auth(response) => (Self::AUTH, to_json(response)?),
+        };
+        Ok((kind, Cow::Owned(json)))
+    }
+
+    pub(crate) fn from_parts(kind: u8, payload: Vec<u8>) -> io::Result<Self> {
+        match kind {
+            Self::HELLO => from_json(&payload).map(Self::Hello),
+            Self::HELLO_ACK => from_json(&payload).map(Self::HelloAck),
+            Self::MEDIA => Ok(Self::Media(payload)),
+            Self::STATS => from_json(&payload).map(Self::Stats),
+            Self::CONTROL => from_json(&payload).map(Self::Control),
+            Self::ERROR => from_json(&payload).map(Self::Error),
+            Self::HEARTBEAT => from_json(&payload).map(Self::Heartbeat),
+            Self::CHALLENGE => from_json(&payload).map(Self::Challenge),
+            Self::AUTH => from_json(&payload).map(Self::Auth),
+            _ => Err(invalid(format!("unknown frame kind {kind}"))),
+        }
+    }
+}
+
+/// Serializes `frame`, header included.
+///
+/// # Errors
+/// If the frame is larger than [`MAX_FRAME_LEN`].
+pub fn encode(frame: &Frame) -> io::Result<Vec<u8>> {
+    let (kind, payload) = frame.to_parts()?;
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+    out.extend_from_slice(&header(kind, &payload)?);
+    out.extend_from_slice(&payload);
+    Ok(out)
+}
+
+/// Serializes `bytes` as a media frame without building a [`Frame`] first.
+///
+/// # Errors
+/// If `bytes` is larger than [`MAX_FRAME_LEN`].
+pub fn encode_media(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(HEADER_LEN + bytes.len());
+    out.extend_from_slice(&media_header(bytes)?);
+    out.extend_from_slice(bytes);
+    Ok(out)
+}
+
+/// Reassembles frames from a byte stream fed in arbitrary chunks.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+}
+
+impl FrameDecoder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Takes the next complete frame out of what was pushed so far.
+    ///
+    /// # Errors
+    /// On a malformed frame; the stream can't be resynchronized after that.
+    pub fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        let Some(header) = self.buffer.first_chunk::<HEADER_LEN>() else {
+            return Ok(None);
+        };
+        let (kind, len) = parse_header(*header)?;
+        let end = HEADER_LEN + len as usize;
+        if self.buffer.len() < end {
+            return Ok(None);
+        }
+        let payload = self.buffer[HEADER_LEN..end].to_vec();
+        self.buffer.drain(..end);
+        Frame::from_parts(kind, payload).map(Some)
+    }
+
+    /// Bytes pushed that don't make a complete frame yet.
+    #[must_use]
+    pub const fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+/// Reads the next frame; `None` if the stream ends cleanly before one starts.
+///
+/// # Errors
+/// On I/O errors, a stream cut mid-frame or a malformed frame.
+pub fn read_frame(reader: &mut impl Read) -> io::Result<Option<Frame>> {
+    let mut header = [0u8; HEADER_LEN];
+    match reader.read_exact(&mut header[..1]) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    reader.read_exact(&mut header[1..])?;
+
+    let (kind, len) = parse_header(header)?;
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    Frame::from_parts(kind, payload).map(Some)
+}
+
+/// # Errors
+/// On I/O errors, or if the frame is larger than [`MAX_FRAME_LEN`].
+pub fn write_frame(writer: &mut impl Write, frame: &Frame) -> io::Result<()> {
+    let (kind, payload) = frame.to_parts()?;
+    writer.write_all(&header(kind, &payload)?)?;
+    writer.write_all(&payload)
+}
+
+/// Writes `bytes` as a media frame without copying them into a [`Frame`].
+///
+/// # Errors
+/// On I/O errors, or if `bytes` is larger than [`MAX_FRAME_LEN`].
+pub fn write_media(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&media_header(bytes)?)?;
+    writer.write_all(bytes)
+}
+
+/// Header of a media frame carrying `bytes`.
+pub(crate) fn media_header(bytes: &[u8]) -> io::Result<[u8; HEADER_LEN]> {
+    header(Frame::MEDIA, bytes)
+}
+
+/// Header of a `kind` frame carrying `payload`, checked against [`MAX_FRAME_LEN`].
+pub(crate) fn header(kind: u8, payload: &[u8]) -> io::Result<[u8; HEADER_LEN]> {
+    let len = u32::try_from(payload.len())
+        .ok()
+        .filter(|len| *len <= MAX_FRAME_LEN)
+        .ok_or_else(|| {
+            invalid(format!(
+                "frame of {} bytes exceeds the limit",
+                payload.len()
+            ))
+        })?;
+    let mut header = [0u8; HEADER_LEN];
+    header[0] = kind;
+    header[1..].copy_from_slice(&len.to_be_bytes());
+    Ok(header)
+}
+
+/// Kind and payload length, checked against [`MAX_FRAME_LEN`].
+pub(crate) fn parse_header(header: [u8; HEADER_LEN]) -> io::Result<(u8, u32)> {
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+    if len > MAX_FRAME_LEN {
+        return Err(invalid(format!("frame of {len} bytes exceeds the limit")));
+    }
+    Ok((header[0], len))
+}
+
+fn to_json<T: Serialize>(value: &T) -> io::Result<Vec<u8>> {
+    serde_json::to_vec(value).map_err(|err| invalid(err.to_string()))
+}
+
+fn from_json<'a, T: Deserialize<'a>>(payload: &'a [u8]) -> io::Result<T> {
+    serde_json::from_slice(payload).map_err(|err| invalid(format!("malformed message: {err}")))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
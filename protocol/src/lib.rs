@@ -0,0 +1,59 @@
+#![warn(clippy::all,
+clippy::pedantic,
+clippy::perf,
+clippy::nursery,
+// clippy::cargo,
+clippy::unwrap_used,
+clippy::expect_used)]
+
+//! Wire protocol between the phone app's bridge (`camera_streamer`) and the
+//! desktop receiver (`video-receiver`).
+//!
+//! A connection starts with [`MAGIC`], followed by frames laid out as
+//! `kind: u8 | length: u32 big-endian | payload`. The sender opens with a
+//! [`Hello`]; a receiver with a pre-shared key sends a [`Challenge`] the sender
+//! must answer (see [`auth`]); the receiver answers with a [`HelloAck`]. Then
+//! media frames carry the container bytes, interleaved with the occasional
+//! stats, control, error or heartbeat message. Messages other than media are
+//! JSON.
+//!
+//! A connection starting with [`EBML_MAGIC`] instead is a legacy sender piping
+//! raw `WebM`, without any framing.
+//!
+//! All of this may run over TLS, the receiver's self-signed certificate pinned
+//! by its [`Fingerprint`].
+//!
+//! Phones find receivers over mDNS (feature `mdns`), or on networks that drop
+//! multicast, by listening for the receivers' UDP broadcast [`beacon`]s.
+
+pub mod auth;
+pub mod beacon;
+mod discovery;
+mod fingerprint;
+mod frame;
+#[cfg(feature = "mdns")]
+pub mod mdns;
+mod message;
+mod pairing;
+#[cfg(feature = "tokio")]
+pub mod tokio_io;
+
+pub use auth::PreSharedKey;
+pub use beacon::Beacon;
+pub use discovery::ReceiverInfo;
+pub use fingerprint::{Fingerprint, InvalidFingerprint, SERVER_NAME};
+pub use frame::{encode, encode_media, read_frame, write_frame, write_media, Frame, FrameDecoder};
+pub use message::{
+    AuthResponse, Challenge, Control, ErrorMessage, Heartbeat, Hello, HelloAck, Stats, StreamParams,
+};
+pub use pairing::{Pairing, PAIRING_PREFIX};
+
+/// First bytes of a framed connection.
+pub const MAGIC: [u8; 4] = *b"CAMS";
+/// First bytes of a raw `WebM`/Matroska stream (EBML header ID).
+pub const EBML_MAGIC: [u8; 4] = [0x1A, 0x45, 0xDF, 0xA3];
+pub const PROTOCOL_VERSION: u16 = 1;
+/// Larger frames are treated as a corrupt stream rather than allocated.
+pub const MAX_FRAME_LEN: u32 = 16 * 1024 * 1024;
+/// `kind` byte plus the payload length.
+pub const HEADER_LEN: usize = 5;
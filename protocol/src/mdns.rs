@@ -0,0 +1,94 @@
+//! Advertising and browsing receivers as a `_camstream._tcp` DNS-SD service
+//! over mDNS.
+//!
+//! Both sides bring their own [`ServiceDaemon`], which keeps answering (or
+//! listening) on its own thread until it is shut down.
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+pub use mdns_sd::{Error, IfKind, ServiceDaemon};
+use mdns_sd::{ServiceEvent, ServiceInfo};
+
+use crate::ReceiverInfo;
+
+pub const SERVICE_TYPE: &str = "_camstream._tcp.local.";
+
+/// Announces `receiver` until the daemon shuts down. Without addresses, those
+/// of every interface are announced, following them as they change.
+///
+/// # Errors
+/// If the name isn't valid in DNS-SD, or the daemon is gone.
+pub fn advertise(daemon: &ServiceDaemon, receiver: &ReceiverInfo) -> Result<(), Error> {
+    let host = format!("{}.local.", host_label(&receiver.name));
+    let service = ServiceInfo::new(
+        SERVICE_TYPE,
+        &receiver.name,
+        &host,
+        &receiver.addresses[..],
+        receiver.port,
+        receiver.txt_properties(),
+    )?;
+    let service = if receiver.addresses.is_empty() {
+        service.enable_addr_auto()
+    } else {
+        service
+    };
+    daemon.register(service)
+}
+
+/// Lists the receivers that answer within `wait`. Blocks all that time:
+/// answers keep coming in as long as one listens.
+///
+/// # Errors
+/// If the daemon is gone.
+pub fn browse(daemon: &ServiceDaemon, wait: Duration) -> Result<Vec<ReceiverInfo>, Error> {
+    let events = daemon.browse(SERVICE_TYPE)?;
+    let deadline = Instant::now() + wait;
+    // By full name: a receiver is resolved again on every announcement
+    let mut found = BTreeMap::new();
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        match events.recv_timeout(left) {
+            Ok(ServiceEvent::ServiceResolved(service)) => {
+                if let Some(receiver) = receiver_info(&service) {
+                    found.insert(service.get_fullname().to_string(), receiver);
+                }
+            }
+            Ok(ServiceEvent::ServiceRemoved(_, fullname)) => {
+                found.remove(&fullname);
+            }
+            Ok(_) => {}
+            // Timed out, or the daemon stopped
+            Err(_) => break,
+        }
+    }
+    let _ = daemon.stop_browse(SERVICE_TYPE);
+    Ok(found.into_values().collect())
+}
+
+fn receiver_info(service: &ServiceInfo) -> Option<ReceiverInfo> {
+    let name = service
+        .get_fullname()
+        .strip_suffix(SERVICE_TYPE)
+        .and_then(|name| name.strip_suffix('.'))?
+        .to_string();
+    let mut addresses: Vec<IpAddr> = service.get_addresses().iter().copied().collect();
+    addresses.sort_by_key(|ip| (ip.is_ipv6(), *ip));
+    let txt = service.get_properties().clone().into_property_map_str();
+    ReceiverInfo::from_txt_properties(name, addresses, service.get_port(), &txt)
+}
+
+/// `name` made usable as a host name: letters, digits and dashes.
+fn host_label(name: &str) -> String {
+    let label: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let label = label.trim_matches('-');
+    if label.is_empty() {
+        "camstream-receiver".to_string()
+    } else {
+        label.to_string()
+    }
+}
@@ -0,0 +1,95 @@
+//! Messages carried as JSON in frames.
+
+use serde::{Deserialize, Serialize};
+
+/// What a stream carries, as announced by the sender and confirmed by the receiver.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamParams {
+    /// e.g. `h264`, `vp8`
+    pub codec: String,
+    /// e.g. `webm`
+    pub container: String,
+    pub width: u32,
+    pub height: u32,
+    pub fps: f64,
+    /// Clockwise rotation, in degrees, that makes the picture upright
+    pub orientation: u16,
+}
+
+/// Sent once by the sender, right after [`MAGIC`](crate::MAGIC).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hello {
+    pub version: u16,
+    pub device_name: String,
+    #[serde(flatten)]
+    pub params: StreamParams,
+}
+
+/// The receiver's answer to a [`Hello`]. Media follows only if `accepted`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HelloAck {
+    /// Version the receiver speaks
+    pub version: u16,
+    pub accepted: bool,
+    /// Why the stream was refused
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// What the receiver will do with the stream (its output size and rate,
+    /// the orientation it applies)
+    #[serde(flatten)]
+    pub params: StreamParams,
+}
+
+/// Sent by the side that requires authentication: answer with an [`AuthResponse`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Challenge {
+    /// Random, hex-encoded
+    pub nonce: String,
+}
+
+/// Proof of knowing the pre-shared key, see [`crate::auth`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthResponse {
+    /// Hex-encoded HMAC-SHA256
+    pub mac: String,
+}
+
+/// How well the link from the sender to the receiver keeps up. The bridge
+/// also shows these in its UI (`bridge-stats` event).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stats {
+    pub conn_id: u64,
+    pub bytes_sent: u64,
+    pub clusters_sent: u64,
+    pub bytes_dropped: u64,
+    pub clusters_dropped: u64,
+    /// Time the last chunk of media waited before being sent
+    pub queue_latency_ms: u64,
+}
+
+/// Asks the other side to change what it sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Control {
+    /// Stop sending media, keeping the connection
+    Pause,
+    Resume,
+    /// Start a new keyframe as soon as possible, e.g. after the decoder lost track
+    RequestKeyframe,
+}
+
+/// Something went wrong on the other side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorMessage {
+    pub message: String,
+    /// The sender of this message is closing the connection
+    pub fatal: bool,
+}
+
+/// Keeps an idle connection alive; `sequence` lets the peer echo it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub sequence: u64,
+    /// Milliseconds since the Unix epoch, by the sender's clock
+    pub timestamp_ms: u64,
+}
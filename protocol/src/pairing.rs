@@ -0,0 +1,50 @@
+//! Pairing codes: everything a phone needs to stream to a receiver, shown by
+//! the receiver as a QR code so nobody has to type an IP address.
+
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Fingerprint;
+
+/// Start of every pairing code, so other QR codes are told apart.
+pub const PAIRING_PREFIX: &str = "camstream:";
+
+/// What a receiver's pairing code carries.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pairing {
+    /// Protocol version the receiver speaks
+    pub version: u16,
+    /// Every address the receiver may be reachable at, the phone picks one
+    pub addresses: Vec<IpAddr>,
+    pub port: u16,
+    /// The receiver's pre-shared key, see [`crate::auth`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub psk: Option<String>,
+    /// Set when the receiver requires TLS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<Fingerprint>,
+}
+
+/// The text to put in the QR code.
+impl fmt::Display for Pairing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        write!(f, "{PAIRING_PREFIX}{json}")
+    }
+}
+
+/// Reads a scanned pairing code; `InvalidData` if it isn't one.
+impl FromStr for Pairing {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let json = s.trim().strip_prefix(PAIRING_PREFIX).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "not a camstream pairing code")
+        })?;
+        serde_json::from_str(json).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
@@ -0,0 +1,44 @@
+//! The blocking frame I/O of the crate root, over tokio streams.
+
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::frame::{header, media_header, parse_header};
+use crate::{Frame, HEADER_LEN};
+
+/// Reads the next frame; `None` if the stream ends cleanly before one starts.
+///
+/// # Errors
+/// On I/O errors, a stream cut mid-frame or a malformed frame.
+pub async fn read_frame(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<Frame>> {
+    let mut header = [0u8; HEADER_LEN];
+    match reader.read_exact(&mut header[..1]).await {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    reader.read_exact(&mut header[1..]).await?;
+
+    let (kind, len) = parse_header(header)?;
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload).await?;
+    Frame::from_parts(kind, payload).map(Some)
+}
+
+/// # Errors
+/// On I/O errors, or if the frame is larger than [`MAX_FRAME_LEN`](crate::MAX_FRAME_LEN).
+pub async fn write_frame(writer: &mut (impl AsyncWrite + Unpin), frame: &Frame) -> io::Result<()> {
+    let (kind, payload) = frame.to_parts()?;
+    writer.write_all(&header(kind, &payload)?).await?;
+    writer.write_all(&payload).await
+}
+
+/// Writes `bytes` as a media frame without copying them into a [`Frame`].
+///
+/// # Errors
+/// On I/O errors, or if `bytes` is larger than [`MAX_FRAME_LEN`](crate::MAX_FRAME_LEN).
+pub async fn write_media(writer: &mut (impl AsyncWrite + Unpin), bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&media_header(bytes)?).await?;
+    writer.write_all(bytes).await
+}
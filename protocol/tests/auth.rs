@@ -0,0 +1,62 @@
+use camstream_protocol::{AuthResponse, Challenge, PreSharedKey};
+
+/// `HMAC-SHA256(key, "camstream auth v1" || nonce)`, as the page computes it
+/// with WebCrypto; the second key is longer than a SHA-256 block.
+#[test]
+fn response_is_the_documented_hmac() {
+    let cases: [(&[u8], String, &str); 2] = [
+        (
+            b"secret",
+            "00".repeat(32),
+            "8ba6de583919b043a8e49c5b817f2088a06083f6a764cd1376f2a5793bd0ef27",
+        ),
+        (
+            &[0xaa; 131],
+            "ab".repeat(32),
+            "42ec7c2a57905ff569dab96bc82850ec116e343abd0080ea196b6d2bd349f30d",
+        ),
+    ];
+    for (key, nonce, expected) in cases {
+        let response = PreSharedKey::new(key)
+            .unwrap()
+            .respond(&Challenge { nonce });
+        assert_eq!(response.mac, expected);
+    }
+}
+
+#[test]
+fn right_key_is_accepted() {
+    let key = PreSharedKey::new("correct horse battery staple").unwrap();
+    let challenge = Challenge::new().unwrap();
+    let response = key.respond(&challenge);
+    assert!(key.verify(&challenge, &response));
+}
+
+#[test]
+fn wrong_key_or_nonce_is_rejected() {
+    let key = PreSharedKey::new("correct horse battery staple").unwrap();
+    let other = PreSharedKey::new("correct horse battery stapler").unwrap();
+    let challenge = Challenge::new().unwrap();
+
+    assert!(!key.verify(&challenge, &other.respond(&challenge)));
+
+    // A response recorded for one challenge is useless for the next
+    let replayed = key.respond(&challenge);
+    let next = Challenge::new().unwrap();
+    assert_ne!(challenge, next);
+    assert!(!key.verify(&next, &replayed));
+
+    for mac in ["", "zz", &"00".repeat(32), &replayed.mac[..62]] {
+        let forged = AuthResponse {
+            mac: mac.to_string(),
+        };
+        assert!(!key.verify(&challenge, &forged), "accepted {mac:?}");
+    }
+}
+
+#[test]
+fn empty_keys_and_debug_output() {
+    assert!(PreSharedKey::new("").is_err());
+    let key = PreSharedKey::new("secret").unwrap();
+    assert!(!format!("{key:?}").contains("secret"));
+}
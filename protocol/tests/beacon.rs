@@ -0,0 +1,135 @@
+use std::net::UdpSocket;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use camstream_protocol::beacon::{MAX_ANNOUNCE_AGE, MAX_BEACON_LEN};
+use camstream_protocol::{Beacon, PreSharedKey, ReceiverInfo};
+
+fn announce() -> Beacon {
+    Beacon::announce(receiver())
+}
+
+fn receiver() -> ReceiverInfo {
+    ReceiverInfo {
+        name: "desk".to_string(),
+        addresses: vec!["192.168.1.20".parse().unwrap()],
+        port: 8080,
+        version: 1,
+        device_path: "/dev/video2".to_string(),
+        width: 480,
+        height: 640,
+        tls: false,
+    }
+}
+
+fn key(key: &str) -> PreSharedKey {
+    PreSharedKey::new(key).unwrap()
+}
+
+#[test]
+fn unsigned_beacon_round_trips() {
+    let beacon = announce();
+    let datagram = beacon.encode(None).unwrap();
+    assert!(!String::from_utf8_lossy(&datagram).contains("mac"));
+    assert_eq!(Beacon::decode(&datagram, None).unwrap(), beacon);
+}
+
+#[test]
+fn signed_beacon_is_accepted_with_the_same_key() {
+    let beacon = announce();
+    let datagram = beacon.encode(Some(&key("correct horse"))).unwrap();
+    assert_eq!(
+        Beacon::decode(&datagram, Some(&key("correct horse"))).unwrap(),
+        beacon
+    );
+    // Phones without a key still list it
+    assert_eq!(Beacon::decode(&datagram, None).unwrap(), beacon);
+}
+
+#[test]
+fn wrong_key_or_missing_signature_is_rejected() {
+    let signed = announce().encode(Some(&key("correct horse"))).unwrap();
+    assert!(Beacon::decode(&signed, Some(&key("battery staple"))).is_err());
+
+    let unsigned = announce().encode(None).unwrap();
+    assert!(Beacon::decode(&unsigned, Some(&key("correct horse"))).is_err());
+}
+
+#[test]
+fn tampered_body_is_rejected() {
+    let signed = announce().encode(Some(&key("correct horse"))).unwrap();
+    let tampered = String::from_utf8(signed)
+        .unwrap()
+        .replace("8080", "9090")
+        .into_bytes();
+    assert!(Beacon::decode(&tampered, Some(&key("correct horse"))).is_err());
+}
+
+#[test]
+fn stale_signed_announcements_are_rejected() {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let psk = key("correct horse");
+    for sent in [
+        now - MAX_ANNOUNCE_AGE * 2,
+        now + MAX_ANNOUNCE_AGE * 2,
+        Duration::ZERO,
+    ] {
+        let replayed = Beacon::Announce {
+            receiver: receiver(),
+            sent_at_ms: u64::try_from(sent.as_millis()).unwrap(),
+        };
+        let datagram = replayed.encode(Some(&psk)).unwrap();
+        assert!(Beacon::decode(&datagram, Some(&psk)).is_err());
+        // Without a key the time can't be trusted anyway
+        assert_eq!(Beacon::decode(&datagram, None).unwrap(), replayed);
+    }
+}
+
+#[test]
+fn announcements_without_a_time_still_decode_unsigned() {
+    let datagram = br#"{"body":{"type":"announce","receiver":{"name":"desk","addresses":[],"port":8080,"version":1,"device_path":"/dev/video2","width":480,"height":640,"tls":false}}}"#;
+    let Beacon::Announce { sent_at_ms, .. } = Beacon::decode(datagram, None).unwrap() else {
+        panic!("expected an announcement");
+    };
+    assert_eq!(sent_at_ms, 0);
+}
+
+#[test]
+fn pings_have_fresh_nonces() {
+    assert_ne!(Beacon::ping().unwrap(), Beacon::ping().unwrap());
+}
+
+#[test]
+fn ping_is_answered_over_udp() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let phone = UdpSocket::bind("127.0.0.1:0").unwrap();
+    phone
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let psk = key("correct horse");
+
+    let ping = Beacon::ping().unwrap();
+    phone
+        .send_to(
+            &ping.encode(Some(&psk)).unwrap(),
+            receiver.local_addr().unwrap(),
+        )
+        .unwrap();
+
+    let mut buffer = [0u8; MAX_BEACON_LEN];
+    let (len, from) = receiver.recv_from(&mut buffer).unwrap();
+    let Beacon::Ping { nonce } = Beacon::decode(&buffer[..len], Some(&psk)).unwrap() else {
+        panic!("expected a ping");
+    };
+    receiver
+        .send_to(&Beacon::Pong { nonce }.encode(Some(&psk)).unwrap(), from)
+        .unwrap();
+
+    let (len, _) = phone.recv_from(&mut buffer).unwrap();
+    let Beacon::Ping { nonce } = ping else {
+        unreachable!()
+    };
+    assert_eq!(
+        Beacon::decode(&buffer[..len], Some(&psk)).unwrap(),
+        Beacon::Pong { nonce }
+    );
+}
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+use camstream_protocol::ReceiverInfo;
+
+fn receiver() -> ReceiverInfo {
+    ReceiverInfo {
+        name: "desk".to_string(),
+        addresses: vec!["192.168.1.20".parse().unwrap()],
+        port: 8080,
+        version: 1,
+        device_path: "/dev/video2".to_string(),
+        width: 480,
+        height: 640,
+        tls: true,
+    }
+}
+
+#[test]
+fn txt_record_round_trips() {
+    let receiver = receiver();
+    let txt = receiver.txt_properties();
+    let back = ReceiverInfo::from_txt_properties(
+        receiver.name.clone(),
+        receiver.addresses.clone(),
+        receiver.port,
+        &txt,
+    );
+    assert_eq!(back, Some(receiver));
+}
+
+#[test]
+fn txt_record_without_version_is_not_a_receiver() {
+    let mut txt = receiver().txt_properties();
+    txt.remove("version");
+    assert_eq!(
+        ReceiverInfo::from_txt_properties("desk".to_string(), Vec::new(), 8080, &txt),
+        None
+    );
+}
+
+#[test]
+fn missing_details_are_left_empty() {
+    let txt = HashMap::from([("version".to_string(), "1".to_string())]);
+    let receiver =
+        ReceiverInfo::from_txt_properties("desk".to_string(), Vec::new(), 8080, &txt).unwrap();
+    assert_eq!(receiver.device_path, "");
+    assert_eq!((receiver.width, receiver.height), (0, 0));
+    assert!(!receiver.tls);
+}
@@ -0,0 +1,43 @@
+use camstream_protocol::Fingerprint;
+
+#[test]
+fn display_and_parse_round_trip() {
+    let fingerprint = Fingerprint::of(b"not really a certificate");
+    let shown = fingerprint.to_string();
+    assert_eq!(shown.len(), 32 * 3 - 1);
+    assert!(shown
+        .chars()
+        .all(|c| c == ':' || c.is_ascii_digit() || c.is_ascii_uppercase()));
+    assert_eq!(shown.parse::<Fingerprint>(), Ok(fingerprint));
+}
+
+#[test]
+fn parsing_is_lenient_about_separators_and_case() {
+    let fingerprint = Fingerprint::of(b"cert");
+    let bare = fingerprint.to_string().replace(':', "");
+    let spaced = fingerprint.to_string().replace(':', " ");
+    for text in [bare.to_lowercase(), bare, spaced] {
+        assert_eq!(text.parse::<Fingerprint>(), Ok(fingerprint), "{text}");
+    }
+}
+
+#[test]
+fn malformed_fingerprints_are_rejected() {
+    let fingerprint = Fingerprint::of(b"cert").to_string();
+    for text in [
+        "",
+        "AB:CD",
+        &fingerprint[3..],
+        &format!("{fingerprint}:00"),
+        &format!("G{}", &fingerprint[1..]),
+    ] {
+        assert!(text.parse::<Fingerprint>().is_err(), "{text}");
+    }
+}
+
+#[test]
+fn matches_only_the_same_certificate() {
+    let fingerprint = Fingerprint::of(b"cert");
+    assert!(fingerprint.matches(b"cert"));
+    assert!(!fingerprint.matches(b"cert2"));
+}
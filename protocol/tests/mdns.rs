@@ -0,0 +1,38 @@
+#![cfg(feature = "mdns")]
+
+use std::time::Duration;
+
+use camstream_protocol::mdns::{self, IfKind, ServiceDaemon};
+use camstream_protocol::ReceiverInfo;
+
+/// A daemon that only talks over the loopback interface, so the test neither
+/// depends on nor disturbs the network.
+fn loopback_daemon() -> ServiceDaemon {
+    let daemon = ServiceDaemon::new().unwrap();
+    daemon.disable_interface(IfKind::All).unwrap();
+    daemon.enable_interface(IfKind::LoopbackV4).unwrap();
+    daemon
+}
+
+#[test]
+fn advertised_receiver_is_browsed() {
+    let receiver = ReceiverInfo {
+        name: format!("camstream test {}", std::process::id()),
+        addresses: vec!["127.0.0.1".parse().unwrap()],
+        port: 18080,
+        version: 1,
+        device_path: "/dev/video2".to_string(),
+        width: 480,
+        height: 640,
+        tls: false,
+    };
+    let responder = loopback_daemon();
+    mdns::advertise(&responder, &receiver).unwrap();
+
+    let browser = loopback_daemon();
+    let found = mdns::browse(&browser, Duration::from_secs(3)).unwrap();
+    assert!(found.contains(&receiver), "{found:?}");
+
+    let _ = browser.shutdown();
+    let _ = responder.shutdown();
+}
@@ -0,0 +1,58 @@
+use camstream_protocol::{Fingerprint, Pairing, PAIRING_PREFIX, PROTOCOL_VERSION};
+
+fn pairing() -> Pairing {
+    Pairing {
+        version: PROTOCOL_VERSION,
+        addresses: vec!["192.168.1.20".parse().unwrap(), "fd00::20".parse().unwrap()],
+        port: 8080,
+        psk: Some("correct horse".to_string()),
+        fingerprint: Some(Fingerprint::of(b"cert")),
+    }
+}
+
+#[test]
+fn pairing_code_round_trips() {
+    let code = pairing().to_string();
+    assert!(code.starts_with(PAIRING_PREFIX));
+    assert_eq!(code.parse::<Pairing>().unwrap(), pairing());
+}
+
+#[test]
+fn optional_fields_are_left_out() {
+    let open = Pairing {
+        psk: None,
+        fingerprint: None,
+        ..pairing()
+    };
+    let code = open.to_string();
+    assert!(
+        !code.contains("psk") && !code.contains("fingerprint"),
+        "{code}"
+    );
+    assert_eq!(code.parse::<Pairing>().unwrap(), open);
+}
+
+#[test]
+fn fingerprint_is_carried_in_its_display_form() {
+    let code = pairing().to_string();
+    assert!(
+        code.contains(&Fingerprint::of(b"cert").to_string()),
+        "{code}"
+    );
+}
+
+#[test]
+fn other_codes_are_rejected() {
+    let json = pairing().to_string()[PAIRING_PREFIX.len()..].to_string();
+    let bad_fingerprint = pairing()
+        .to_string()
+        .replace(&Fingerprint::of(b"cert").to_string(), "AB:CD");
+    for code in [
+        "https://example.com",
+        "camstream:{}",
+        &json,
+        &bad_fingerprint,
+    ] {
+        assert!(code.parse::<Pairing>().is_err(), "{code}");
+    }
+}
@@ -0,0 +1,198 @@
+use std::io::{Cursor, ErrorKind};
+
+use camstream_protocol::{
+    encode, encode_media, read_frame, write_frame, write_media, AuthResponse, Challenge, Control,
+    ErrorMessage, Frame, FrameDecoder, Heartbeat, Hello, HelloAck, Stats, StreamParams, HEADER_LEN,
+    MAX_FRAME_LEN, PROTOCOL_VERSION,
+};
+
+fn params() -> StreamParams {
+    StreamParams {
+        codec: "h264".to_string(),
+        container: "webm".to_string(),
+        width: 640,
+        height: 480,
+        fps: 29.97,
+        orientation: 90,
+    }
+}
+
+/// One frame of every kind.
+fn frames() -> Vec<Frame> {
+    vec![
+        Frame::Hello(Hello {
+            version: PROTOCOL_VERSION,
+            device_name: "Pixel 7".to_string(),
+            params: params(),
+        }),
+        Frame::HelloAck(HelloAck {
+            version: PROTOCOL_VERSION,
+            accepted: true,
+            reason: None,
+            params: params(),
+        }),
+        Frame::HelloAck(HelloAck {
+            version: PROTOCOL_VERSION,
+            accepted: false,
+            reason: Some("unsupported codec".to_string()),
+            params: params(),
+        }),
+        Frame::Media(vec![0x1A, 0x45, 0xDF, 0xA3, 0x00, 0xFF]),
+        Frame::Media(Vec::new()),
+        Frame::Stats(Stats {
+            conn_id: 3,
+            bytes_sent: 1 << 40,
+            clusters_sent: 12,
+            bytes_dropped: 512,
+            clusters_dropped: 1,
+            queue_latency_ms: 40,
+        }),
+        Frame::Control(Control::Pause),
+        Frame::Control(Control::Resume),
+        Frame::Control(Control::RequestKeyframe),
+        Frame::Error(ErrorMessage {
+            message: "decoder failed".to_string(),
+            fatal: true,
+        }),
+        Frame::Heartbeat(Heartbeat {
+            sequence: 7,
+            timestamp_ms: 1_700_000_000_000,
+        }),
+        Frame::Challenge(Challenge {
+            nonce: "00ff".repeat(16),
+        }),
+        Frame::Auth(AuthResponse {
+            mac: "ab".repeat(32),
+        }),
+    ]
+}
+
+fn stream() -> Vec<u8> {
+    frames()
+        .iter()
+        .flat_map(|frame| encode(frame).unwrap())
+        .collect()
+}
+
+#[test]
+fn blocking_io_round_trips_every_frame() {
+    let mut wire = Vec::new();
+    for frame in frames() {
+        write_frame(&mut wire, &frame).unwrap();
+    }
+    assert_eq!(wire, stream());
+
+    let mut reader = Cursor::new(wire);
+    for expected in frames() {
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(expected));
+    }
+    assert_eq!(read_frame(&mut reader).unwrap(), None);
+}
+
+#[test]
+fn decoder_reassembles_frames_split_anywhere() {
+    let wire = stream();
+    for chunk_len in [1, 2, 5, 7, 64, wire.len()] {
+        let mut decoder = FrameDecoder::new();
+        let mut decoded = Vec::new();
+        for chunk in wire.chunks(chunk_len) {
+            decoder.push(chunk);
+            while let Some(frame) = decoder.next_frame().unwrap() {
+                decoded.push(frame);
+            }
+        }
+        assert_eq!(decoded, frames(), "chunks of {chunk_len} bytes");
+        assert_eq!(decoder.pending(), 0);
+    }
+}
+
+#[test]
+fn media_helpers_match_media_frames() {
+    let bytes = vec![1, 2, 3, 4, 5];
+    let framed = encode(&Frame::Media(bytes.clone())).unwrap();
+    assert_eq!(encode_media(&bytes).unwrap(), framed);
+
+    let mut wire = Vec::new();
+    write_media(&mut wire, &bytes).unwrap();
+    assert_eq!(wire, framed);
+}
+
+#[test]
+fn stream_cut_mid_frame_is_an_error() {
+    let wire = encode(&frames()[0]).unwrap();
+    let mut reader = Cursor::new(&wire[..wire.len() - 1]);
+    let err = read_frame(&mut reader).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn oversized_frames_are_rejected() {
+    let mut wire = vec![3];
+    wire.extend_from_slice(&(MAX_FRAME_LEN + 1).to_be_bytes());
+    let err = read_frame(&mut Cursor::new(&wire)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&wire);
+    assert!(decoder.next_frame().is_err());
+
+    let too_big = vec![0; MAX_FRAME_LEN as usize + 1];
+    assert!(encode_media(&too_big).is_err());
+}
+
+#[test]
+fn unknown_kinds_and_malformed_json_are_rejected() {
+    let mut unknown = vec![0xEE];
+    unknown.extend_from_slice(&0u32.to_be_bytes());
+    let err = read_frame(&mut Cursor::new(&unknown)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    let json = b"{\"version\":1}";
+    let mut truncated_hello = vec![1];
+    truncated_hello.extend_from_slice(&(json.len() as u32).to_be_bytes());
+    truncated_hello.extend_from_slice(json);
+    assert_eq!(truncated_hello.len(), HEADER_LEN + json.len());
+    let err = read_frame(&mut Cursor::new(&truncated_hello)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn hello_json_is_flat() {
+    let Frame::Hello(hello) = &frames()[0] else {
+        unreachable!()
+    };
+    let json: serde_json::Value = serde_json::to_value(hello).unwrap();
+    assert_eq!(json["device_name"], "Pixel 7");
+    assert_eq!(json["codec"], "h264");
+    assert_eq!(json["orientation"], 90);
+    // Accepted acks leave the reason out entirely
+    let Frame::HelloAck(ack) = &frames()[1] else {
+        unreachable!()
+    };
+    let json = serde_json::to_value(ack).unwrap();
+    assert!(json.get("reason").is_none());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn tokio_io_round_trips_every_frame() {
+    use camstream_protocol::tokio_io;
+
+    let mut wire = Vec::new();
+    for frame in frames() {
+        match &frame {
+            Frame::Media(bytes) => tokio_io::write_media(&mut wire, bytes).await.unwrap(),
+            other => tokio_io::write_frame(&mut wire, other).await.unwrap(),
+        }
+    }
+    assert_eq!(wire, stream());
+
+    let mut reader = wire.as_slice();
+    for expected in frames() {
+        assert_eq!(
+            tokio_io::read_frame(&mut reader).await.unwrap(),
+            Some(expected)
+        );
+    }
+    assert_eq!(tokio_io::read_frame(&mut reader).await.unwrap(), None);
+}
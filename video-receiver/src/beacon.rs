@@ -0,0 +1,163 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use camstream_protocol::beacon::{BEACON_INTERVAL, MAX_BEACON_LEN};
+use camstream_protocol::{Beacon, PreSharedKey, ReceiverInfo};
+use if_addrs::IfAddr;
+
+/// Recent ping nonces remembered so a replayed ping goes unanswered.
+const ANSWERED_NONCES: usize = 256;
+
+/// Background thread broadcasting the receiver on the LAN for networks that
+/// drop mDNS, and answering the pings phones time the round trip with.
+pub struct Beacons {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Beacons {
+    /// Starts announcing `receiver` to `port` on every local IPv4 network,
+    /// signed with `psk` if there is one.
+    pub fn spawn(receiver: ReceiverInfo, port: u16, psk: Option<PreSharedKey>) -> Result<Self> {
+        // Fail now rather than every second in the thread
+        Beacon::announce(receiver.clone())
+            .encode(psk.as_ref())
+            .context("Error encoding the beacon")?;
+        // Any port: phones answer to wherever the announcement came from
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+            .context("Error binding the beacon socket")?;
+        socket
+            .set_broadcast(true)
+            .context("Error enabling broadcast on the beacon socket")?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let handle = thread::spawn(move || {
+            let mut next_announcement = Instant::now();
+            let mut buffer = [0u8; MAX_BEACON_LEN];
+            let mut answered = VecDeque::with_capacity(ANSWERED_NONCES);
+            while !thread_stop.load(Ordering::Relaxed) {
+                let now = Instant::now();
+                if now >= next_announcement {
+                    announce(&socket, &receiver, port, psk.as_ref());
+                    next_announcement = now + BEACON_INTERVAL;
+                }
+
+                // Answer pings until the next announcement is due
+                let wait = next_announcement.saturating_duration_since(Instant::now());
+                // A zero timeout is refused rather than meaning "don't wait"
+                if let Err(err) = socket.set_read_timeout(Some(wait.max(BEACON_INTERVAL / 100))) {
+                    eprintln!("Error setting the beacon timeout: {err}");
+                    break;
+                }
+                match socket.recv_from(&mut buffer) {
+                    Ok((len, from)) => {
+                        answer(&socket, &buffer[..len], from, psk.as_ref(), &mut answered);
+                    }
+                    Err(err) if is_timeout(&err) => {}
+                    Err(err) => eprintln!("Error receiving on the beacon socket: {err}"),
+                }
+            }
+        });
+
+        Ok(Self {
+            stop,
+            handle: Some(handle),
+        })
+    }
+}
+
+/// Broadcasts a freshly stamped announcement of `receiver` on every network
+/// the computer is on; phones reject signed ones that have gone stale.
+fn announce(socket: &UdpSocket, receiver: &ReceiverInfo, port: u16, psk: Option<&PreSharedKey>) {
+    let announcement = match Beacon::announce(receiver.clone()).encode(psk) {
+        Ok(announcement) => announcement,
+        Err(err) => {
+            eprintln!("Error encoding the beacon: {err}");
+            return;
+        }
+    };
+    for address in broadcast_addresses() {
+        if let Err(err) = socket.send_to(&announcement, (address, port)) {
+            eprintln!("Error broadcasting the beacon to {address}: {err}");
+        }
+    }
+}
+
+/// Broadcast address of each IPv4 interface. Falls back to 255.255.255.255,
+/// which only goes out the interface of the default route, if there are none.
+fn broadcast_addresses() -> Vec<Ipv4Addr> {
+    let interfaces = if_addrs::get_if_addrs().unwrap_or_else(|err| {
+        eprintln!("Error listing network interfaces: {err}");
+        Vec::new()
+    });
+    let mut addresses: Vec<Ipv4Addr> = interfaces
+        .iter()
+        .filter(|iface| !iface.is_loopback())
+        .filter_map(|iface| match &iface.addr {
+            IfAddr::V4(v4) => v4.broadcast,
+            IfAddr::V6(_) => None,
+        })
+        .collect();
+    addresses.sort_unstable();
+    addresses.dedup();
+    if addresses.is_empty() {
+        addresses.push(Ipv4Addr::BROADCAST);
+    }
+    addresses
+}
+
+/// Pongs a ping, signed with `psk` if there is one. Anything else, our own
+/// broadcasts and pings already `answered` included, is ignored.
+fn answer(
+    socket: &UdpSocket,
+    datagram: &[u8],
+    from: SocketAddr,
+    psk: Option<&PreSharedKey>,
+    answered: &mut VecDeque<String>,
+) {
+    // With a key, only phones that know it get an answer: otherwise anyone
+    // could have the receiver send pongs to any address
+    let Ok(Beacon::Ping { nonce }) = Beacon::decode(datagram, psk) else {
+        return;
+    };
+    if answered.contains(&nonce) {
+        return;
+    }
+    if answered.len() == ANSWERED_NONCES {
+        answered.pop_front();
+    }
+    answered.push_back(nonce.clone());
+    let pong = match (Beacon::Pong { nonce }).encode(psk) {
+        Ok(pong) => pong,
+        Err(err) => {
+            eprintln!("Error encoding the pong: {err}");
+            return;
+        }
+    };
+    if let Err(err) = socket.send_to(&pong, from) {
+        eprintln!("Error answering the ping from {from}: {err}");
+    }
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+impl Drop for Beacons {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
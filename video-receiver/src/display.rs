@@ -1,18 +1,26 @@
-use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
 use ffmpeg_next as ffmpeg;
-use ffmpeg_next::codec::Parameters;
+use ffmpeg_next::codec::{Parameters, packet};
 use ffmpeg_next::{
-    Rational, Rescale,
-    codec::Id as CodecId,
-    format, frame,
-    software::scaling::{context::Context as Scaler, flag::Flags as ScaleFlags},
+    Rational, Rescale, frame,
     util::{color::Range as ColorRange, format::pixel::Pixel},
 };
 
+use crate::error::ReceiverError;
+use crate::idle::{DEFAULT_SLATE_TEXT, IdleSource, render_text};
+use crate::output_format::OutputFormat;
+use crate::scale::{FrameScaler, ScaleAlgorithm, ScaleMode};
+use crate::sink::{FrameSink, SinkConfig, V4l2Sink};
+use crate::transform::{FrameTransformer, Transform};
+
+/// Camera shared between the connection loop and the keep-alive pump.
+pub type SharedCamera = Arc<Mutex<VideoVirtualCamera>>;
+
 pub struct VideoVirtualCamera {
-    output_context: Option<ffmpeg::format::context::Output>,
-    encoder: Option<ffmpeg::codec::encoder::video::Encoder>,
-    out_stream_index: Option<usize>,
+    sink: Box<dyn FrameSink>,
+    sink_open: bool,
     in_time_base: Option<Rational>,
     enc_time_base: Option<Rational>,
     detected_avg_fps: Option<Rational>,
@@ -21,18 +29,45 @@ pub struct VideoVirtualCamera {
     params: Option<Parameters>,
 
     // New fields for virtual camera specifics:
-    device_path: String,    // e.g., "/dev/video2"
-    use_mjpeg: bool,        // true -> MJPEG, false -> RAWVIDEO (YUYV)
-    target_pix_fmt: Pixel,  // yuv420p for MJPEG, yuyv422 for rawvideo
-    scaler: Option<Scaler>, // frame format/size converter
+    output_format: OutputFormat, // codec + pixel format written to the sink
+    scaler: FrameScaler,         // frame format/size converter
+
+    // Orientation, applied before scaling
+    transform: Transform,                  // set by the user
+    autorotate: bool,                      // also honor the sender's display matrix
+    stream_orientation: Option<Transform>, // display matrix of the current stream, if any
+    sender_orientation: Option<Transform>, // announced in the sender's hello, if any
+    transformer: FrameTransformer,
+
+    // Output timeline, kept continuous across sender reconnects
+    next_pts: i64,                  // in enc_time_base units
+    stream_pts_offset: Option<i64>, // maps the current stream's pts onto the output timeline
+    streaming: bool,                // a sender is connected and frames are flowing
+
+    idle_source: IdleSource,
+    last_output: Option<frame::Video>, // last frame written, already converted
+    idle_frame: Option<frame::Video>,  // idle_source rendered in the output format
 }
 
 impl VideoVirtualCamera {
-    pub fn new(width: u32, height: u32, device_path: impl Into<String>) -> Result<Self> {
+    /// Camera writing to the v4l2loopback device at `device_path` (e.g. "/dev/video2").
+    pub fn new(
+        width: u32,
+        height: u32,
+        device_path: impl Into<String>,
+    ) -> Result<Self, ReceiverError> {
+        Self::with_sink(width, height, Box::new(V4l2Sink::new(device_path)))
+    }
+
+    /// Camera writing to an arbitrary [`FrameSink`].
+    pub fn with_sink(
+        width: u32,
+        height: u32,
+        sink: Box<dyn FrameSink>,
+    ) -> Result<Self, ReceiverError> {
         Ok(Self {
-            output_context: None,
-            encoder: None,
-            out_stream_index: None,
+            sink,
+            sink_open: false,
             in_time_base: None,
             enc_time_base: None,
             detected_avg_fps: None,
@@ -40,30 +75,134 @@ impl VideoVirtualCamera {
             height,
             params: None,
 
-            device_path: device_path.into(),
-            use_mjpeg: true,                // default to MJPEG for compatibility
-            target_pix_fmt: Pixel::YUV420P, // good default for MJPEG
-            scaler: None,
+            output_format: OutputFormat::default(),
+            scaler: FrameScaler::new(
+                width,
+                height,
+                OutputFormat::default().pixel_format(),
+                OutputFormat::default().is_full_range(),
+            ),
+
+            transform: Transform::default(),
+            autorotate: true,
+            stream_orientation: None,
+            sender_orientation: None,
+            transformer: FrameTransformer::new(),
+
+            next_pts: 0,
+            stream_pts_offset: None,
+            streaming: false,
+
+            idle_source: IdleSource::default(),
+            last_output: None,
+            idle_frame: None,
         })
     }
 
-    // Optionally let callers switch to rawvideo (YUYV) if needed:
+    /// Selects the codec/pixel format written to the sink, after checking the
+    /// encoder supports it. Must be called before the output is opened.
+    pub fn set_output_format(&mut self, output_format: OutputFormat) -> Result<(), ReceiverError> {
+        if self.sink_open {
+            return Err(ReceiverError::Config(
+                "Output format can't change once the output is open".to_string(),
+            ));
+        }
+        output_format.validate()?;
+        self.output_format = output_format;
+        self.scaler
+            .set_output_format(output_format.pixel_format(), output_format.is_full_range());
+        self.idle_frame = None;
+        Ok(())
+    }
+
+    /// Switches the output to raw YUYV 4:2:2.
+    #[deprecated(note = "use `set_output_format(OutputFormat::Yuyv422)`")]
     pub fn use_rawvideo_yuyv422(&mut self) {
-        self.use_mjpeg = false;
-        self.target_pix_fmt = Pixel::YUYV422;
+        if let Err(err) = self.set_output_format(OutputFormat::Yuyv422) {
+            eprintln!("Error switching to YUYV output: {err}");
+        }
+    }
+
+    /// Changes what is shown while no sender is connected; takes effect on the next idle frame.
+    pub fn set_idle_source(&mut self, idle_source: IdleSource) {
+        self.idle_source = idle_source;
+        self.idle_frame = None;
+    }
+
+    pub fn set_scale_mode(&mut self, mode: ScaleMode) {
+        self.scaler.set_mode(mode);
+        self.idle_frame = None;
+    }
+
+    pub fn set_scale_algorithm(&mut self, algorithm: ScaleAlgorithm) {
+        self.scaler.set_algorithm(algorithm);
+        self.idle_frame = None;
+    }
+
+    /// Color of the bars added by [`ScaleMode::Fit`], as RGB.
+    pub fn set_bar_color(&mut self, rgb: [u8; 3]) {
+        self.scaler.set_bar_color(rgb);
+        self.idle_frame = None;
+    }
+
+    /// Rotation/mirroring applied to incoming frames; can change at any time.
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// Whether the rotation in the sender's display matrix side data is applied
+    /// (before [`Self::set_transform`]'s). On by default.
+    pub fn set_autorotate(&mut self, autorotate: bool) {
+        self.autorotate = autorotate;
+    }
+
+    /// Orientation the sender announced for its stream, used when the stream
+    /// itself carries no display matrix. Set per connection (`None` to clear).
+    pub fn set_sender_orientation(&mut self, orientation: Option<Transform>) {
+        self.sender_orientation = orientation;
+    }
+
+    /// Fixes the output frame rate up front instead of taking it from the first stream.
+    /// Has no effect once the output is open.
+    pub fn set_frame_rate(&mut self, fps: Rational) {
+        if self.sink_open || fps.numerator() <= 0 || fps.denominator() <= 0 {
+            return;
+        }
+        self.detected_avg_fps = Some(fps);
+        self.enc_time_base = Some(fps.invert());
     }
 
     pub fn set_parameters(&mut self, params: Parameters) {
         self.params = Some(params);
     }
 
-    // Call once before frames: propagates time base and fps
+    // Call once per incoming stream, before its frames: propagates time base and fps
     pub fn prepare_from_stream(&mut self, in_stream: &ffmpeg::format::stream::Stream) {
         let in_tb = in_stream.time_base();
         let avg_fr = in_stream.avg_frame_rate(); // may be 0/0 on VFR
 
         self.in_time_base = Some(in_tb);
 
+        // A reconnecting sender may use a different size/format and restarts its pts
+        self.scaler.reset();
+        self.stream_pts_offset = None;
+        self.stream_orientation = in_stream
+            .side_data()
+            .find(|sd| sd.kind() == packet::side_data::Type::DisplayMatrix)
+            .and_then(|sd| Transform::from_display_matrix(sd.data()));
+        if let Some(orientation) = self.stream_orientation {
+            println!("🧭 Stream orientation: {orientation}");
+        }
+
+        // Once the output is open its timing is fixed; later streams are mapped onto it
+        if self.sink_open {
+            return;
+        }
+
         let has_cfr = avg_fr.numerator() > 0 && avg_fr.denominator() > 0;
         if has_cfr {
             self.detected_avg_fps = Some(avg_fr);
@@ -75,275 +214,194 @@ impl VideoVirtualCamera {
         }
     }
 
-    pub fn show_frame(&mut self, frame: &ffmpeg::frame::Video) -> Result<()> {
-        if self.output_context.is_none() {
-            self.init_virtual_camera(frame)?;
-        }
+    pub fn show_frame(&mut self, frame: &ffmpeg::frame::Video) -> Result<(), ReceiverError> {
+        self.open()?;
+        self.streaming = true;
         self.render_frame(frame)?;
         Ok(())
     }
 
-    fn ensure_scaler(&mut self, src_w: u32, src_h: u32, src_fmt: Pixel) -> Result<()> {
-        if self.scaler.is_none() {
-            // Create scaler to convert to target size/format expected by encoder/device
-            let scale = Scaler::get(
-                src_fmt,
-                src_w,
-                src_h,
-                self.target_pix_fmt,
-                self.width,
-                self.height,
-                ScaleFlags::BILINEAR,
-            )?;
-            self.scaler = Some(scale);
-        }
-        Ok(())
+    /// The sender went away: keep the output open and let [`Self::show_idle_frame`] fill in.
+    pub fn end_stream(&mut self) {
+        self.streaming = false;
     }
 
-    fn init_virtual_camera(&mut self, first_frame: &ffmpeg::frame::Video) -> Result<()> {
-        let enc_tb = self.enc_time_base.ok_or_else(|| {
-            anyhow::anyhow!(
-                "enc_time_base not set. Call prepare_from_stream() before sending frames."
-            )
-        })?;
+    /// True while the output is open but nothing is feeding it.
+    pub fn is_idle(&self) -> bool {
+        self.sink_open && !self.streaming
+    }
+
+    /// Time between two output frames.
+    pub fn frame_interval(&self) -> Duration {
+        let fps = self.detected_avg_fps.unwrap_or(Rational::new(30, 1));
+        if fps.numerator() <= 0 || fps.denominator() <= 0 {
+            return Duration::from_secs(1) / 30;
+        }
+        Duration::from_secs_f64(f64::from(fps.denominator()) / f64::from(fps.numerator()))
+    }
 
-        // Open v4l2 output: explicit muxer name is "video4linux2" (short name "v4l2" also works)
-        let mut output = format::output_as(&self.device_path, "video4linux2")?;
+    /// Writes one idle frame (see [`IdleSource`]) if the camera is idle.
+    pub fn show_idle_frame(&mut self) -> Result<(), ReceiverError> {
+        if !self.is_idle() {
+            return Ok(());
+        }
 
-        // Choose codec: MJPEG or RAWVIDEO
-        let codec_id = if self.use_mjpeg {
-            ffmpeg::codec::Id::MJPEG
-        } else {
-            ffmpeg::codec::Id::RAWVIDEO
+        let mut idle = match (&self.idle_source, &self.last_output) {
+            (IdleSource::LastFrame, Some(last)) => last.clone(),
+            _ => self.idle_frame()?,
         };
+        idle.set_pts(Some(self.next_pts));
+        self.next_pts += 1;
 
-        let codec = ffmpeg::encoder::find(codec_id)
-            .ok_or_else(|| anyhow::anyhow!("Requested encoder not found: {:?}", codec_id))?;
-
-        // Build encoder context
-        let mut enc_ctx = ffmpeg::codec::context::Context::new_with_codec(codec)
-            .encoder()
-            .video()?;
-
-        // Set negotiated size/pix_fmt (v4l2loopback will accept many, but MJPEG+YUV420P is a safe default)
-        enc_ctx.set_width(self.width.max(1));
-        enc_ctx.set_height(self.height.max(1));
-        enc_ctx.set_format(self.target_pix_fmt);
-
-        // FPS -> frame_rate + time_base
-        if let Some(avg_fps) = self.detected_avg_fps {
-            enc_ctx.set_frame_rate(Some((
-                avg_fps.numerator() as i32,
-                avg_fps.denominator() as i32,
-            )));
+        self.sink.write_frame(&idle)
+    }
+
+    fn idle_frame(&mut self) -> Result<frame::Video, ReceiverError> {
+        if let Some(idle) = &self.idle_frame {
+            return Ok(idle.clone());
         }
-        enc_ctx.set_time_base(enc_tb);
 
-        // Optional: tune mjpeg
-        // enc_ctx.set_bit_rate(8_000_000); // tweak as desired
+        // A missing/broken image shouldn't stop the keep-alive; it is only reported once
+        // since the fallback gets cached like the real thing would have been
+        let still = match self.idle_source.render(self.width, self.height) {
+            Ok(Some(still)) => still,
+            Ok(None) => render_text(DEFAULT_SLATE_TEXT, self.width, self.height),
+            Err(err) => {
+                eprintln!("Error rendering idle source {}: {err:#}", self.idle_source);
+                render_text(DEFAULT_SLATE_TEXT, self.width, self.height)
+            }
+        };
+
+        // Same scaling as live frames (an image keeps its aspect ratio), and let
+        // swscale produce the right colors for the output format/range
+        let mut idle = frame::Video::empty();
+        self.scaler
+            .with_same_settings()
+            .run(&still, &mut idle)
+            .map_err(ReceiverError::Scale)?;
+        if self.output_format.is_full_range() {
+            idle.set_color_range(ColorRange::JPEG);
+        }
 
-        // For v4l2 output, we still add a stream so avformat can negotiate correctly
-        let encoder = enc_ctx.open_as(codec)?;
+        // Kept even for "last frame", which may show it for hours before a
+        // sender connects; the first real frame drops it
+        self.idle_frame = Some(idle.clone());
+        Ok(idle)
+    }
 
-        let stream_index;
-        {
-            let mut stream = output.add_stream(codec)?;
-            stream.set_parameters(&encoder);
-            stream_index = stream.index();
+    /// Opens the output right away instead of on the first frame, so idle
+    /// frames can be shown before any sender connects.
+    pub fn open(&mut self) -> Result<(), ReceiverError> {
+        if self.sink_open {
+            return Ok(());
         }
 
-        // v4l2 output has no global header/trailer needs, but writing header is fine
-        output.write_header()?;
+        let enc_tb = self.enc_time_base.ok_or_else(|| {
+            ReceiverError::Config(
+                "enc_time_base not set. Call prepare_from_stream() or set_frame_rate() first."
+                    .to_string(),
+            )
+        })?;
 
-        // Prime scaler if needed
-        self.ensure_scaler(
-            first_frame.width(),
-            first_frame.height(),
-            first_frame.format(),
-        )?;
+        self.sink.open(&SinkConfig {
+            width: self.width,
+            height: self.height,
+            pixel_format: self.output_format.pixel_format(),
+            codec: self.output_format.codec_id(),
+            quality: self.output_format.quality(),
+            time_base: enc_tb,
+            frame_rate: self.detected_avg_fps,
+        })?;
 
-        self.out_stream_index = Some(stream_index);
-        self.encoder = Some(encoder);
-        self.output_context = Some(output);
+        self.sink_open = true;
 
         Ok(())
     }
 
-    // fn render_frame(&mut self, frame: &ffmpeg::frame::Video) -> Result<()> {
-    //     if let (Some(encoder), Some(output_context)) = (&mut self.encoder, &mut self.output_context)
-    //     {
-    //         let enc_tb = self.enc_time_base.expect("enc_time_base should be set");
-    //         let in_tb = self.in_time_base.expect("in_time_base should be set");
-    //
-    //         // Convert to target format/size if needed
-    //         {
-    //             let src_w = frame.width();
-    //             let src_h = frame.height();
-    //             let src_fmt = frame.format();
-    //             if self.scaler.is_none() {
-    //                 // Create scaler to convert to target size/format expected by encoder/device
-    //                 let scale = Scaler::get(
-    //                     src_fmt,
-    //                     src_w,
-    //                     src_h,
-    //                     self.target_pix_fmt,
-    //                     self.width,
-    //                     self.height,
-    //                     ScaleFlags::BILINEAR,
-    //                 )?;
-    //                 self.scaler = Some(scale);
-    //             }
-    //             Result::<(), anyhow::Error>::Ok(())
-    //         }?;
-    //         let scaler = self.scaler.as_mut().unwrap();
-    //
-    //         let mut converted = frame::Video::empty();
-    //         converted.set_format(self.target_pix_fmt);
-    //         converted.set_width(self.width);
-    //         converted.set_height(self.height);
-    //
-    //         scaler.run(frame, &mut converted)?;
-    //
-    //         // PTS handling: rescale to encoder time base
-    //         if let Some(src_pts) = frame.pts() {
-    //             let dst_pts = src_pts.rescale(in_tb, enc_tb);
-    //             converted.set_pts(Some(dst_pts));
-    //         }
-    //
-    //         // Send to encoder
-    //         encoder.send_frame(&converted)?;
-    //
-    //         // Drain packets
-    //         let mut packet = ffmpeg::Packet::empty();
-    //         while encoder.receive_packet(&mut packet).is_ok() {
-    //             let out_idx = self.out_stream_index.unwrap_or(0);
-    //             let out_tb = output_context.stream(out_idx).unwrap().time_base();
-    //
-    //             // For devices, timestamps are not always pivotal, but keep them consistent
-    //             packet.rescale_ts(enc_tb, out_tb);
-    //             packet.set_stream(out_idx);
-    //             packet.write_interleaved(output_context)?;
-    //         }
-    //     }
-    //     Ok(())
-    // }
-
-    fn to_full_range_for_mjpeg(pix: Pixel) -> Pixel {
-        match pix {
-            Pixel::YUV420P => Pixel::YUVJ420P,
-            Pixel::YUV422P => Pixel::YUVJ422P,
-            Pixel::YUV444P => Pixel::YUVJ444P,
-            // Common semi-planar inputs -> go to a JPEG-compatible target
-            Pixel::NV12 | Pixel::NV21 => Pixel::YUVJ420P,
-            // If already full-range "yuvj", keep it
-            Pixel::YUVJ420P | Pixel::YUVJ422P | Pixel::YUVJ444P => pix,
-            // Default: keep original (if not YUV or not compatible, swscale will still convert)
-            other => other,
-        }
+    /// Pixel format of the frames handed to the sink.
+    fn output_pix_fmt(&self) -> Pixel {
+        self.output_format.pixel_format()
     }
 
-    fn is_mjpeg_encoder(enc: &ffmpeg::codec::encoder::video::Encoder) -> bool {
-        enc.codec()
-            .map(|c| c.id() == CodecId::MJPEG)
-            .unwrap_or(false)
+    /// The user's transform, preceded by whatever turns the sender's picture upright.
+    fn effective_transform(&self, input: &frame::Video) -> Transform {
+        if !self.autorotate {
+            return self.transform;
+        }
+        // Per-frame side data wins over the stream's, the orientation may change mid-stream
+        input
+            .side_data(frame::side_data::Type::DisplayMatrix)
+            .and_then(|sd| Transform::from_display_matrix(sd.data()))
+            .or(self.stream_orientation)
+            .or(self.sender_orientation)
+            .map_or(self.transform, |upright| upright.then(self.transform))
     }
 
-    pub fn render_frame(&mut self, frame: &ffmpeg::frame::Video) -> Result<()> {
-        if let (Some(encoder), Some(output_context)) = (&mut self.encoder, &mut self.output_context)
-        {
-            let enc_tb = self.enc_time_base.expect("enc_time_base should be set");
-            let in_tb = self.in_time_base.expect("in_time_base should be set");
+    pub fn render_frame(&mut self, frame: &ffmpeg::frame::Video) -> Result<(), ReceiverError> {
+        if self.sink_open {
+            let enc_tb = self.enc_time_base.ok_or_else(|| {
+                ReceiverError::Config("enc_time_base not set, yet the output is open".to_string())
+            })?;
+            let in_tb = self.in_time_base.ok_or_else(|| {
+                ReceiverError::Config(
+                    "in_time_base not set. Call prepare_from_stream() before rendering frames."
+                        .to_string(),
+                )
+            })?;
+
+            // Rotate/mirror first so the scaler fits the upright picture
+            let transformed = self
+                .transformer
+                .apply(frame, self.effective_transform(frame))
+                .map_err(ReceiverError::Scale)?;
+            let frame = transformed.as_ref().unwrap_or(frame);
+
+            // Decide the destination pixel format
+            let dest_pix_fmt = self.output_pix_fmt();
 
-            // Decide the destination pixel format:
-            // - If encoding to MJPEG, use a full-range "yuvj" format so the encoder gets full-range YUV.
-            // - Otherwise, keep the configured target format.
-            let dest_pix_fmt = if Self::is_mjpeg_encoder(encoder) {
-                Self::to_full_range_for_mjpeg(self.target_pix_fmt)
-            } else {
-                self.target_pix_fmt
-            };
-
-            // Build/rebuild scaler if needed
-            {
-                let src_w = frame.width();
-                let src_h = frame.height();
-                let src_fmt = frame.format();
-
-                // If your struct stores 'self.scaler' only, we at least ensure it's created.
-                // If you want to be strict about re-creation when out fmt changes, optionally
-                // store 'self.scaler_out_fmt' and recreate when it differs from dest_pix_fmt.
-                if self.scaler.is_none() {
-                    let scale = Scaler::get(
-                        src_fmt,
-                        src_w,
-                        src_h,
-                        dest_pix_fmt,
-                        self.width,
-                        self.height,
-                        ScaleFlags::BILINEAR,
-                    )?;
-                    self.scaler = Some(scale);
-                }
-            }
-            let scaler = self.scaler.as_mut().unwrap();
-
-            // Prepare destination frame with JPEG/full-range metadata.
             let mut converted = frame::Video::empty();
             converted.set_format(dest_pix_fmt);
             converted.set_width(self.width);
             converted.set_height(self.height);
 
-            // Hint the source range to the scaler if it’s present on the input frame.
-            // This increases the chance swscale will expand limited -> full as part of conversion.
-            // (If the source doesn't have it set, swscale will assume limited for typical YUV.)
-            converted.set_color_range(frame.color_range()); // swscale will consult both frames’ metadata
-
-            // Do the scaling/pixfmt conversion (this is where range expansion occurs if needed)
-            scaler.run(frame, &mut converted)?;
-
-            // Now explicitly mark the output as full range (JPEG) for correctness and encoder compliance.
-            converted.set_color_range(ColorRange::JPEG);
-
-            // PTS handling: rescale to encoder time base
-            if let Some(src_pts) = frame.pts() {
-                let dst_pts = src_pts.rescale(in_tb, enc_tb);
-                converted.set_pts(Some(dst_pts));
+            // Do the scaling/pixfmt conversion, expanding to full range when the output format is
+            self.scaler
+                .run(frame, &mut converted)
+                .map_err(ReceiverError::Scale)?;
+            if self.output_format.is_full_range() {
+                converted.set_color_range(ColorRange::JPEG);
             }
 
-            // Send to encoder
-            encoder.send_frame(&converted)?;
-
-            // Drain packets
-            let mut packet = ffmpeg::Packet::empty();
-            while encoder.receive_packet(&mut packet).is_ok() {
-                let out_idx = self.out_stream_index.unwrap_or(0);
-                let out_tb = output_context.stream(out_idx).unwrap().time_base();
-                packet.rescale_ts(enc_tb, out_tb);
-                packet.set_stream(out_idx);
-                packet.write_interleaved(output_context)?;
+            // PTS handling: rescale to encoder time base, then shift onto the output timeline
+            // so it keeps increasing across reconnects and idle frames
+            let dst_pts = match frame.pts() {
+                Some(src_pts) => {
+                    let rescaled = src_pts.rescale(in_tb, enc_tb);
+                    let offset = *self
+                        .stream_pts_offset
+                        .get_or_insert(self.next_pts - rescaled);
+                    (rescaled + offset).max(self.next_pts)
+                }
+                None => self.next_pts,
+            };
+            converted.set_pts(Some(dst_pts));
+            self.next_pts = dst_pts + 1;
+
+            self.sink.write_frame(&converted)?;
+            if self.idle_source == IdleSource::LastFrame {
+                self.last_output = Some(converted);
+                // Shown instead from now on
+                self.idle_frame = None;
             }
         }
         Ok(())
     }
 
-    pub fn finish(&mut self) -> Result<()> {
-        if let (Some(encoder), Some(output_context)) = (&mut self.encoder, &mut self.output_context)
-        {
-            let enc_tb = self.enc_time_base.unwrap_or(Rational(1, 1000));
-            let out_idx = self.out_stream_index.unwrap_or(0);
-            let out_tb = output_context.stream(out_idx).unwrap().time_base();
-
-            encoder.send_eof()?;
-
-            let mut packet = ffmpeg::Packet::empty();
-            while encoder.receive_packet(&mut packet).is_ok() {
-                packet.rescale_ts(enc_tb, out_tb);
-                packet.set_stream(out_idx);
-                packet.write_interleaved(output_context)?;
-            }
-
-            // v4l2 doesn’t need a trailer, but calling it is harmless
-            output_context.write_trailer()?;
+    pub fn finish(&mut self) -> Result<(), ReceiverError> {
+        if self.sink_open {
+            self.sink_open = false;
+            self.streaming = false;
+            self.sink.finish()?;
         }
         Ok(())
     }
@@ -354,3 +412,128 @@ impl Drop for VideoVirtualCamera {
         let _ = self.finish();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::sink::MemorySink;
+
+    /// A mid-gray YUV 4:2:0 frame, like the decoder hands out.
+    fn decoded_frame(width: u32, height: u32, pts: i64) -> frame::Video {
+        let mut frame = frame::Video::new(Pixel::YUV420P, width, height);
+        for plane in 0..3 {
+            frame.data_mut(plane).fill(128);
+        }
+        frame.set_pts(Some(pts));
+        frame
+    }
+
+    /// Camera streaming at 30 fps into `sink`, as if `prepare_from_stream` had run.
+    fn camera(sink: Box<dyn FrameSink>) -> VideoVirtualCamera {
+        let mut camera = VideoVirtualCamera::with_sink(64, 48, sink).unwrap();
+        camera.set_output_format(OutputFormat::Yuyv422).unwrap();
+        camera.set_frame_rate(Rational::new(30, 1));
+        camera.in_time_base = Some(Rational::new(1, 30));
+        camera
+    }
+
+    #[test]
+    fn decoded_frames_reach_the_sink_converted() {
+        let sink = MemorySink::new(4);
+        let frames = sink.frames();
+        let mut camera = camera(Box::new(sink));
+
+        camera.show_frame(&decoded_frame(32, 24, 10)).unwrap();
+        camera.show_frame(&decoded_frame(32, 24, 11)).unwrap();
+
+        let frames = frames.lock().unwrap();
+        assert_eq!(frames.len(), 2);
+        for (pts, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.format(), Pixel::YUYV422);
+            assert_eq!((frame.width(), frame.height()), (64, 48));
+            // The output timeline starts at 0 whatever the sender's pts
+            assert_eq!(frame.pts(), Some(pts as i64));
+        }
+    }
+
+    #[test]
+    fn nothing_is_written_before_the_output_opens() {
+        let sink = MemorySink::new(4);
+        let frames = sink.frames();
+        let mut camera = camera(Box::new(sink));
+
+        camera.render_frame(&decoded_frame(32, 24, 0)).unwrap();
+        assert!(frames.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn last_frame_caches_the_slate_until_a_real_frame_arrives() {
+        let sink = MemorySink::new(8);
+        let frames = sink.frames();
+        let mut camera = camera(Box::new(sink));
+        camera.set_idle_source(IdleSource::LastFrame);
+        camera.open().unwrap();
+
+        // Nothing shown yet: the slate, rendered once
+        camera.show_idle_frame().unwrap();
+        assert!(camera.idle_frame.is_some());
+        camera.show_idle_frame().unwrap();
+
+        camera.show_frame(&decoded_frame(32, 24, 0)).unwrap();
+        assert!(camera.idle_frame.is_none());
+        camera.end_stream();
+        camera.show_idle_frame().unwrap();
+
+        let frames = frames.lock().unwrap();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].data(0), frames[1].data(0));
+        assert_eq!(frames[3].data(0), frames[2].data(0));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn use_rawvideo_yuyv422_selects_yuyv() {
+        let mut camera = camera(Box::new(MemorySink::new(1)));
+        camera
+            .set_output_format(OutputFormat::Mjpeg { quality: None })
+            .unwrap();
+        camera.use_rawvideo_yuyv422();
+        assert_eq!(camera.output_format, OutputFormat::Yuyv422);
+    }
+
+    /// Counts the calls to `finish`.
+    struct FinishCounter(Arc<AtomicUsize>);
+
+    impl FrameSink for FinishCounter {
+        fn open(&mut self, _config: &SinkConfig) -> Result<(), ReceiverError> {
+            Ok(())
+        }
+
+        fn write_frame(&mut self, _frame: &frame::Video) -> Result<(), ReceiverError> {
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), ReceiverError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn finish_is_idempotent() {
+        let finished = Arc::new(AtomicUsize::new(0));
+        let mut camera = camera(Box::new(FinishCounter(Arc::clone(&finished))));
+
+        // Never opened: nothing to flush
+        camera.finish().unwrap();
+        assert_eq!(finished.load(Ordering::SeqCst), 0);
+
+        camera.open().unwrap();
+        camera.finish().unwrap();
+        camera.finish().unwrap();
+        drop(camera);
+        assert_eq!(finished.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,59 @@
+use ffmpeg_next as ffmpeg;
+use ffmpeg_next::error::EAGAIN;
+
+/// Failures of the receive pipeline, by stage, so callers can tell a dead
+/// connection from a bad frame or a broken output.
+#[derive(Debug, thiserror::Error)]
+pub enum ReceiverError {
+    /// Accepting a sender (or setting up its connection) failed
+    #[error("accepting a sender failed: {0}")]
+    Accept(std::io::Error),
+
+    /// The sender vanished mid-stream: its connection was reset or cut off
+    /// in the middle of a frame, rather than closed once it was done
+    #[error("{peer} disconnected")]
+    Disconnected { peer: std::net::SocketAddr },
+
+    /// The sender didn't get through the protocol handshake
+    #[error("handshake with {peer} failed: {reason}")]
+    Handshake {
+        peer: std::net::SocketAddr,
+        reason: String,
+    },
+
+    /// The incoming container couldn't be read
+    #[error("demuxing failed: {0}")]
+    Demux(ffmpeg::Error),
+
+    #[error("decoding failed: {0}")]
+    Decode(ffmpeg::Error),
+
+    /// Rotating, resizing or converting a frame failed
+    #[error("scaling failed: {0}")]
+    Scale(ffmpeg::Error),
+
+    #[error("encoding failed: {0}")]
+    Encode(ffmpeg::Error),
+
+    /// The output (device, file, ...) rejected the frames
+    #[error("sink failed: {0}")]
+    Sink(ffmpeg::Error),
+
+    /// The camera was set up or used out of order
+    #[error("{0}")]
+    Config(String),
+}
+
+/// The codec can't take more input (or has no output) until the other side is drained.
+pub fn is_again(err: &ffmpeg::Error) -> bool {
+    matches!(err, ffmpeg::Error::Other { errno } if *errno == EAGAIN)
+}
+
+pub fn is_eof(err: &ffmpeg::Error) -> bool {
+    matches!(err, ffmpeg::Error::Eof)
+}
+
+/// EAGAIN and EOF are part of the send/receive dance, not failures.
+pub fn is_benign(err: &ffmpeg::Error) -> bool {
+    is_again(err) || is_eof(err)
+}
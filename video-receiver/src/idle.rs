@@ -0,0 +1,269 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use embedded_graphics::{
+    Drawable, Pixel as DrawPixel,
+    mono_font::{MonoTextStyle, ascii::FONT_10X20},
+    pixelcolor::Rgb888,
+    prelude::{DrawTarget, OriginDimensions, Point, RgbColor, Size},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+use ffmpeg_next as ffmpeg;
+use ffmpeg_next::{frame, media, util::format::pixel::Pixel};
+
+/// Slate shown before the first sender connects when nothing else is configured.
+pub const DEFAULT_SLATE_TEXT: &str = "Waiting for phone...";
+
+/// What the virtual camera shows while no sender is connected.
+///
+/// Parsed from `last`, `black`, `color:RRGGBB`, `image:PATH` or `text:MESSAGE`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum IdleSource {
+    /// Keep repeating the last frame received (a "waiting for phone" slate until there is one)
+    #[default]
+    LastFrame,
+    Color([u8; 3]),
+    /// PNG/JPEG (or anything else FFmpeg decodes), fitted to the output like live frames
+    Image(PathBuf),
+    /// White text centered on a dark background
+    Text(String),
+}
+
+impl FromStr for IdleSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "last" => Ok(Self::LastFrame),
+            None if s == "black" => Ok(Self::Color([0, 0, 0])),
+            Some(("color", hex)) => parse_hex_color(hex).map(Self::Color),
+            Some(("image", path)) if !path.is_empty() => Ok(Self::Image(PathBuf::from(path))),
+            Some(("text", text)) => Ok(Self::Text(text.to_string())),
+            _ => Err(format!(
+                "invalid idle source '{s}', expected last, black, color:RRGGBB, image:PATH or text:MESSAGE"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for IdleSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LastFrame => write!(f, "last"),
+            Self::Color([r, g, b]) => write!(f, "color:{r:02x}{g:02x}{b:02x}"),
+            Self::Image(path) => write!(f, "image:{}", path.display()),
+            Self::Text(text) => write!(f, "text:{text}"),
+        }
+    }
+}
+
+/// Parses `RRGGBB`, with or without a leading `#`.
+pub fn parse_hex_color(hex: &str) -> Result<[u8; 3], String> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid color '{hex}', expected RRGGBB"));
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| e.to_string());
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+impl IdleSource {
+    /// Renders the still for this source at `width`×`height`, in whatever
+    /// pixel format is convenient; the camera converts it to the output format.
+    /// Returns `None` for [`IdleSource::LastFrame`], which has nothing to render.
+    pub fn render(&self, width: u32, height: u32) -> Result<Option<frame::Video>> {
+        match self {
+            Self::LastFrame => Ok(None),
+            Self::Color(rgb) => Ok(Some(solid_rgb(*rgb, width, height))),
+            Self::Image(path) => decode_image(path).map(Some),
+            Self::Text(text) => Ok(Some(render_text(text, width, height))),
+        }
+    }
+}
+
+pub fn solid_rgb(rgb: [u8; 3], width: u32, height: u32) -> frame::Video {
+    RgbCanvas::new(width, height, rgb).to_frame()
+}
+
+/// Renders `text` with the built-in bitmap font, upscaled to stay readable at any output size.
+pub fn render_text(text: &str, width: u32, height: u32) -> frame::Video {
+    const GLYPH_W: u32 = 10;
+    const GLYPH_H: u32 = 20;
+    const BACKGROUND: [u8; 3] = [0x20, 0x20, 0x20];
+
+    let lines = text.lines().count().max(1) as u32;
+    let columns = text
+        .lines()
+        .map(|l| l.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(1) as u32;
+
+    // Largest integer zoom that keeps the text within 80% of the width and a third of the height
+    let zoom = ((width * 4 / 5) / (columns * GLYPH_W))
+        .min((height / 3) / (lines * GLYPH_H))
+        .clamp(1, 8);
+
+    let mut small = RgbCanvas::new(width.div_ceil(zoom), height.div_ceil(zoom), BACKGROUND);
+    let center = Point::new((small.width / 2) as i32, (small.height / 2) as i32);
+    let style = MonoTextStyle::new(&FONT_10X20, Rgb888::WHITE);
+    let layout = TextStyleBuilder::new()
+        .alignment(Alignment::Center)
+        .baseline(Baseline::Middle)
+        .build();
+    // Drawing into memory cannot fail
+    let _ = Text::with_text_style(text, center, style, layout).draw(&mut small);
+
+    small.zoom(zoom, width, height).to_frame()
+}
+
+/// Decodes the first video frame of `path` (a still image, normally).
+fn decode_image(path: &Path) -> Result<frame::Video> {
+    let mut ictx = ffmpeg::format::input(path)
+        .with_context(|| format!("Couldn't open idle image {}", path.display()))?;
+
+    let stream = ictx
+        .streams()
+        .best(media::Type::Video)
+        .ok_or_else(|| anyhow::anyhow!("{} has no image in it", path.display()))?;
+    let stream_index = stream.index();
+    let mut decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .video()?;
+
+    let mut image = frame::Video::empty();
+    for (stream, packet) in ictx.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+        decoder.send_packet(&packet)?;
+        if decoder.receive_frame(&mut image).is_ok() {
+            return Ok(image);
+        }
+    }
+
+    decoder.send_eof()?;
+    decoder
+        .receive_frame(&mut image)
+        .with_context(|| format!("Couldn't decode idle image {}", path.display()))?;
+    Ok(image)
+}
+
+/// Packed RGB24 buffer that embedded-graphics can draw on.
+struct RgbCanvas {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl RgbCanvas {
+    /// Never empty: a zero width or height is taken as 1.
+    fn new(width: u32, height: u32, fill: [u8; 3]) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        Self {
+            width,
+            height,
+            data: fill.repeat((width * height) as usize),
+        }
+    }
+
+    /// Nearest-neighbour upscale by `zoom`, cropped to `width`×`height`.
+    fn zoom(&self, zoom: u32, width: u32, height: u32) -> Self {
+        let zoom = zoom.max(1);
+        let mut out = Self::new(width, height, [0, 0, 0]);
+        for y in 0..out.height {
+            let src_row = ((y / zoom).min(self.height - 1) * self.width) as usize * 3;
+            let dst_row = (y * out.width) as usize * 3;
+            for x in 0..out.width {
+                let src = src_row + (x / zoom).min(self.width - 1) as usize * 3;
+                let dst = dst_row + x as usize * 3;
+                out.data[dst..dst + 3].copy_from_slice(&self.data[src..src + 3]);
+            }
+        }
+        out
+    }
+
+    fn to_frame(&self) -> frame::Video {
+        let mut frame = frame::Video::new(Pixel::RGB24, self.width, self.height);
+        let stride = frame.stride(0);
+        let row_len = self.width as usize * 3;
+        let dst = frame.data_mut(0);
+        for (y, row) in self.data.chunks_exact(row_len).enumerate() {
+            dst[y * stride..y * stride + row_len].copy_from_slice(row);
+        }
+        frame
+    }
+}
+
+impl OriginDimensions for RgbCanvas {
+    fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+}
+
+impl DrawTarget for RgbCanvas {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = DrawPixel<Self::Color>>,
+    {
+        for DrawPixel(point, color) in pixels {
+            let (Ok(x), Ok(y)) = (u32::try_from(point.x), u32::try_from(point.y)) else {
+                continue;
+            };
+            if x >= self.width || y >= self.height {
+                continue;
+            }
+            let offset = ((y * self.width + x) * 3) as usize;
+            self.data[offset..offset + 3].copy_from_slice(&[color.r(), color.g(), color.b()]);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colors() {
+        let cases = [
+            ("#ff8000", Ok([0xff, 0x80, 0x00])),
+            ("ff8000", Ok([0xff, 0x80, 0x00])),
+            ("#A0b1C2", Ok([0xa0, 0xb1, 0xc2])),
+            ("", Err(())),
+            ("#", Err(())),
+            ("#fff", Err(())),
+            ("#ff80001", Err(())),
+            ("##ff8000", Err(())),
+            ("#gg0000", Err(())),
+            ("#ff 800", Err(())),
+            // Six bytes but not six hex digits
+            ("#ffé00", Err(())),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_hex_color(input).map_err(|_| ()), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn zero_sizes_render_a_single_pixel() {
+        for (width, height) in [(0, 0), (0, 48), (64, 0)] {
+            let canvas = RgbCanvas::new(width, height, [1, 2, 3]);
+            assert_eq!((canvas.width, canvas.height), (width.max(1), height.max(1)));
+            let zoomed = canvas.zoom(0, width, height);
+            assert_eq!(zoomed.data, [1, 2, 3].repeat(zoomed.data.len() / 3));
+
+            let frame = render_text(DEFAULT_SLATE_TEXT, width, height);
+            assert_eq!(
+                (frame.width(), frame.height()),
+                (width.max(1), height.max(1))
+            );
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::io::{self, PipeReader, PipeWriter, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::os::fd::AsRawFd;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use camstream_protocol::{
+    Challenge, EBML_MAGIC, Frame, Hello, HelloAck, MAGIC, PreSharedKey, read_frame, write_frame,
+};
+use ffmpeg_next::{self as ffmpeg, Dictionary};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+use crate::error::ReceiverError;
+
+/// A sender that connects but doesn't get through the handshake (TLS included)
+/// within this is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// First byte of a TLS record carrying a handshake message, i.e. of a ClientHello.
+const TLS_HANDSHAKE: u8 = 0x16;
+
+/// The connection as the protocol sees it, decrypted if it uses TLS.
+trait Transport: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Transport for T {}
+
+/// Reads and writes through `inner`, failing with `TimedOut` once `deadline`
+/// has passed. A timeout per read alone would let a sender trickling a byte
+/// at a time hold up the accept loop, and every other sender, forever.
+struct Deadline<'a, T> {
+    inner: T,
+    /// The connection under `inner`, whose timeouts shrink to the time left
+    socket: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl<T> Deadline<'_, T> {
+    /// Bounds the next socket operation by the time left. Both directions:
+    /// TLS reads may have to write, and the other way round.
+    fn arm(&self) -> io::Result<()> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(timed_out());
+        }
+        self.socket.set_read_timeout(Some(left))?;
+        self.socket.set_write_timeout(Some(left))
+    }
+}
+
+impl<T: Read> Read for Deadline<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.arm()?;
+        self.inner.read(buf).map_err(explain_timeout)
+    }
+}
+
+impl<T: Write> Write for Deadline<'_, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.arm()?;
+        self.inner.write(buf).map_err(explain_timeout)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.arm()?;
+        self.inner.flush().map_err(explain_timeout)
+    }
+}
+
+fn timed_out() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        "took too long to get through the handshake",
+    )
+}
+
+/// Socket timeouts surface as `WouldBlock` ("Resource temporarily unavailable") on Unix.
+fn explain_timeout(err: io::Error) -> io::Error {
+    match err.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => timed_out(),
+        _ => err,
+    }
+}
+
+/// One connected sender, its media flowing through a pipe FFmpeg reads from.
+///
+/// A thread copies the media out of the socket (unwrapping protocol frames, or
+/// as-is for a legacy raw stream), so FFmpeg only ever sees the container.
+pub struct Ingest {
+    peer: SocketAddr,
+    /// `None` for a legacy sender that streams raw WebM without a handshake
+    hello: Option<Hello>,
+    /// The raw connection, kept to time out and shut down whatever runs over it
+    socket: TcpStream,
+    /// Read end of the pipe; `None` only while dropping
+    media: Option<PipeReader>,
+    pump: Option<JoinHandle<()>>,
+    /// Set by the pump, before FFmpeg sees the media end, if the sender vanished
+    vanished: Arc<AtomicBool>,
+}
+
+impl Ingest {
+    /// Waits for the next sender on `listener` and works out what it speaks.
+    /// Framed senders get `acknowledge`'s answer to their hello, once they
+    /// proved they know `psk` if there is one; a refused sender is an error.
+    /// With a `tls` config, senders must connect over TLS; without one, TLS is refused.
+    pub fn accept(
+        listener: &TcpListener,
+        tls: Option<&Arc<ServerConfig>>,
+        psk: Option<&PreSharedKey>,
+        acknowledge: impl FnOnce(&Hello) -> HelloAck,
+    ) -> Result<Self, ReceiverError> {
+        let (socket, peer) = listener.accept().map_err(ReceiverError::Accept)?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        let handshake_err = |reason: String| ReceiverError::Handshake { peer, reason };
+        println!("🔌 Sender connected from {peer}");
+
+        socket
+            .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+            .map_err(ReceiverError::Accept)?;
+        let mut first = [0u8; 1];
+        socket
+            .peek(&mut first)
+            .map_err(|err| handshake_err(err.to_string()))?;
+        let mut stream: Box<dyn Transport> = match (first[0] == TLS_HANDSHAKE, tls) {
+            (true, Some(config)) => {
+                let connection = ServerConnection::new(Arc::clone(config))
+                    .map_err(|err| handshake_err(err.to_string()))?;
+                let socket = socket.try_clone().map_err(ReceiverError::Accept)?;
+                // The TLS handshake itself runs on the first read
+                Box::new(StreamOwned::new(connection, socket))
+            }
+            (true, None) => {
+                return Err(handshake_err(
+                    "sender uses TLS, but this receiver runs without --tls".to_string(),
+                ));
+            }
+            (false, Some(_)) => {
+                return Err(handshake_err(
+                    "plaintext connection refused, this receiver requires TLS".to_string(),
+                ));
+            }
+            (false, None) => Box::new(socket.try_clone().map_err(ReceiverError::Accept)?),
+        };
+        let mut handshake = Deadline {
+            inner: &mut stream,
+            socket: &socket,
+            deadline,
+        };
+        let mut magic = [0u8; 4];
+        handshake
+            .read_exact(&mut magic)
+            .map_err(|err| handshake_err(err.to_string()))?;
+
+        let hello = match magic {
+            MAGIC => {
+                let hello = match read_frame(&mut handshake) {
+                    Ok(Some(Frame::Hello(hello))) => hello,
+                    Ok(Some(_)) => return Err(handshake_err("expected a hello".to_string())),
+                    Ok(None) => return Err(handshake_err("closed before its hello".to_string())),
+                    Err(err) => return Err(handshake_err(err.to_string())),
+                };
+                let authenticated = match psk {
+                    Some(psk) => authenticate(&mut handshake, psk)
+                        .map_err(|err| handshake_err(format!("authentication: {err}")))?,
+                    None => true,
+                };
+                let mut ack = acknowledge(&hello);
+                if !authenticated {
+                    ack.accepted = false;
+                    ack.reason = Some("authentication failed, wrong pre-shared key".to_string());
+                }
+                write_frame(&mut handshake, &Frame::HelloAck(ack.clone()))
+                    .map_err(|err| handshake_err(err.to_string()))?;
+                if !ack.accepted {
+                    return Err(handshake_err(format!(
+                        "refused: {}",
+                        ack.reason.as_deref().unwrap_or("no reason given")
+                    )));
+                }
+                Some(hello)
+            }
+            EBML_MAGIC if psk.is_some() => {
+                return Err(handshake_err(
+                    "legacy raw stream refused, it can't prove it knows the pre-shared key"
+                        .to_string(),
+                ));
+            }
+            EBML_MAGIC => None,
+            _ => {
+                return Err(handshake_err(format!(
+                    "unknown stream start {magic:02x?}, neither the protocol nor WebM"
+                )));
+            }
+        };
+        // From here on the sender may pause as long as it likes
+        socket
+            .set_read_timeout(None)
+            .and_then(|()| socket.set_write_timeout(None))
+            .map_err(ReceiverError::Accept)?;
+
+        let (media, pipe) = io::pipe().map_err(ReceiverError::Accept)?;
+        let framed = hello.is_some();
+        let vanished = Arc::new(AtomicBool::new(false));
+        let pump_vanished = Arc::clone(&vanished);
+        let pump = std::thread::spawn(move || {
+            let mut pipe = pipe;
+            // The legacy stream's first bytes were consumed while sniffing
+            let prefix = if framed { &[][..] } else { &EBML_MAGIC[..] };
+            match pump_media(stream, framed, prefix, &mut pipe) {
+                Ok(()) => {}
+                // FFmpeg stopped reading, the connection is being torn down
+                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {}
+                Err(err) if is_disconnect(&err) => pump_vanished.store(true, Ordering::Release),
+                Err(err) => eprintln!("Error reading from sender {peer}: {err}"),
+            }
+            // Only now does FFmpeg see the end of the media
+            drop(pipe);
+        });
+
+        Ok(Self {
+            peer,
+            hello,
+            socket,
+            media: Some(media),
+            pump: Some(pump),
+            vanished,
+        })
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub fn hello(&self) -> Option<&Hello> {
+        self.hello.as_ref()
+    }
+
+    /// Whether the sender vanished rather than closing its stream. Only
+    /// meaningful once FFmpeg has seen the media end.
+    pub fn vanished(&self) -> bool {
+        self.vanished.load(Ordering::Acquire)
+    }
+
+    /// `err` as [`ReceiverError::Disconnected`] if the sender vanished: all
+    /// FFmpeg sees of that is its input running dry.
+    pub fn blame(&self, err: ReceiverError) -> ReceiverError {
+        if self.vanished() {
+            ReceiverError::Disconnected { peer: self.peer }
+        } else {
+            err
+        }
+    }
+
+    /// Opens the sender's media as an FFmpeg input, which probes the container itself.
+    pub fn open_input(&self) -> Result<ffmpeg::format::context::Input, ReceiverError> {
+        let media = self
+            .media
+            .as_ref()
+            .ok_or_else(|| ReceiverError::Config("sender connection already closed".to_string()))?;
+        // `fd:` dups the descriptor, the pipe stays ours to close
+        let mut options = Dictionary::new();
+        options.set("fd", &media.as_raw_fd().to_string());
+        ffmpeg::format::input_with_dictionary(&"fd:", options)
+            .map_err(|err| self.blame(ReceiverError::Demux(err)))
+    }
+}
+
+impl Drop for Ingest {
+    fn drop(&mut self) {
+        // Unblocks the pump whether it waits on the sender or on a full pipe
+        let _ = self.socket.shutdown(Shutdown::Both);
+        drop(self.media.take());
+        if let Some(pump) = self.pump.take()
+            && pump.join().is_err()
+        {
+            eprintln!("Reader thread for {} panicked", self.peer);
+        }
+    }
+}
+
+/// Challenges the sender to prove it knows `psk`.
+fn authenticate(stream: &mut impl Transport, psk: &PreSharedKey) -> io::Result<bool> {
+    let challenge = Challenge::new()?;
+    write_frame(stream, &Frame::Challenge(challenge.clone()))?;
+    match read_frame(stream)? {
+        Some(Frame::Auth(response)) => Ok(psk.verify(&challenge, &response)),
+        Some(other) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("expected an answer to the challenge, got {other:?}"),
+        )),
+        None => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "closed before answering the challenge",
+        )),
+    }
+}
+
+/// The connection was reset, or cut off in the middle of a frame (or of a
+/// TLS record), as when the sender's network goes away.
+fn is_disconnect(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+    )
+}
+
+/// Copies the sender's media into `pipe` until the connection ends.
+fn pump_media(
+    mut stream: Box<dyn Transport>,
+    framed: bool,
+    prefix: &[u8],
+    pipe: &mut PipeWriter,
+) -> io::Result<()> {
+    pipe.write_all(prefix)?;
+    if !framed {
+        io::copy(&mut stream, pipe)?;
+        return Ok(());
+    }
+    while let Some(frame) = read_frame(&mut stream)? {
+        match frame {
+            Frame::Media(bytes) => pipe.write_all(&bytes)?,
+            // Nothing to answer; the stream itself shows whether the sender is alive
+            Frame::Heartbeat(_) | Frame::Stats(_) => {}
+            Frame::Error(error) if error.fatal => {
+                eprintln!("Sender gave up: {}", error.message);
+                break;
+            }
+            Frame::Error(error) => eprintln!("Sender reported: {}", error.message),
+            other => eprintln!("Ignoring unexpected {other:?} mid-stream"),
+        }
+    }
+    Ok(())
+}
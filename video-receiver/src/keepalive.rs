@@ -0,0 +1,59 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crate::display::SharedCamera;
+
+/// Background thread feeding idle frames to the camera while no sender is
+/// connected, so video apps keep seeing a live device between connections.
+pub struct KeepAlive {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl KeepAlive {
+    pub fn spawn(camera: SharedCamera) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+
+        let handle = thread::spawn(move || {
+            let mut next_tick = Instant::now();
+            while !thread_stop.load(Ordering::Relaxed) {
+                let interval = match camera.lock() {
+                    Ok(mut camera) => {
+                        if let Err(err) = camera.show_idle_frame() {
+                            eprintln!("Error writing idle frame: {err}");
+                        }
+                        camera.frame_interval()
+                    }
+                    // The connection loop panicked while holding the camera; nothing left to feed
+                    Err(_) => break,
+                };
+
+                // Schedule against the clock so the idle stream keeps the nominal frame rate
+                next_tick += interval;
+                let now = Instant::now();
+                if next_tick > now {
+                    thread::sleep(next_tick - now);
+                } else {
+                    next_tick = now;
+                }
+            }
+        });
+
+        Self {
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -1 +1,13 @@
+pub mod beacon;
 pub mod display;
+pub mod error;
+pub mod idle;
+pub mod ingest;
+pub mod keepalive;
+pub mod output_format;
+pub mod pairing;
+pub mod record;
+pub mod scale;
+pub mod sink;
+pub mod tls;
+pub mod transform;
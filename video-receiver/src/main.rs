@@ -1,8 +1,31 @@
-use anyhow::Result;
-use clap::Parser;
-use ffmpeg_next::{self as ffmpeg, Dictionary};
+use std::io::BufRead;
+use std::net::{TcpListener, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
 
-use video_receiver::display::VideoVirtualCamera;
+use anyhow::{Context, Result};
+use camstream_protocol::beacon::DEFAULT_BEACON_PORT;
+use camstream_protocol::mdns::{self, ServiceDaemon};
+use camstream_protocol::{
+    Hello, HelloAck, PROTOCOL_VERSION, Pairing, PreSharedKey, ReceiverInfo, StreamParams,
+};
+use clap::{Parser, ValueEnum};
+use ffmpeg_next::{self as ffmpeg, Rational};
+
+use video_receiver::beacon::Beacons;
+use video_receiver::display::{SharedCamera, VideoVirtualCamera};
+use video_receiver::error::{ReceiverError, is_again, is_benign};
+use video_receiver::idle::{IdleSource, parse_hex_color};
+use video_receiver::ingest::Ingest;
+use video_receiver::keepalive::KeepAlive;
+use video_receiver::output_format::OutputFormat;
+use video_receiver::pairing;
+use video_receiver::record::StreamRecorder;
+use video_receiver::scale::{ScaleAlgorithm, ScaleMode};
+use video_receiver::sink::{FileSink, FrameSink, NullSink, V4l2Sink};
+use video_receiver::tls::TlsIdentity;
+use video_receiver::transform::{Rotation, Transform};
 
 #[derive(Parser)]
 #[command(name = "video-receiver")]
@@ -12,10 +35,51 @@ struct Args {
     #[arg(short, long, default_value = "8080")]
     port: u16,
 
-    /// Host to bind to(currently unused, sorry :'[)
+    /// Address to bind to (IPv4, IPv6 or a hostname such as `localhost`)
     #[arg(long, default_value = "0.0.0.0")]
     host: String,
 
+    /// Pre-shared key senders must prove they know. Without one, anyone who
+    /// can reach the port can stream. Prefer the environment variable: the
+    /// command line is visible to other users
+    #[arg(long, env = "CAMSTREAM_PSK", hide_env_values = true)]
+    psk: Option<String>,
+
+    /// Require senders to connect over TLS. The certificate is self-signed,
+    /// phones pin it by the fingerprint printed at startup
+    #[arg(long)]
+    tls: bool,
+
+    /// Where the TLS certificate and key are kept (generated on first run)
+    /// [default: ~/.config/video-receiver]
+    #[arg(long, requires = "tls")]
+    tls_dir: Option<PathBuf>,
+
+    /// Don't print the pairing QR code (it contains the --psk, if any)
+    #[arg(long)]
+    no_qr: bool,
+
+    /// Also save the pairing QR code as a PNG image
+    #[arg(long)]
+    qr_png: Option<PathBuf>,
+
+    /// Name phones list this receiver under [default: the host name]
+    #[arg(long)]
+    name: Option<String>,
+
+    /// Don't advertise this receiver on the network (mDNS, `_camstream._tcp`)
+    #[arg(long)]
+    no_mdns: bool,
+
+    /// Also broadcast UDP beacons, for networks that block the multicast mDNS
+    /// needs. Signed with the --psk, if any
+    #[arg(long)]
+    beacon: bool,
+
+    /// UDP port beacons are broadcast to
+    #[arg(long, requires = "beacon", default_value_t = DEFAULT_BEACON_PORT)]
+    beacon_port: u16,
+
     /// Window width
     #[arg(short, long, default_value = "480")]
     width: u32,
@@ -27,54 +91,447 @@ struct Args {
     /// Virtual Camera device path
     #[arg(short, long, default_value = "/dev/video2")]
     device_path: String,
+
+    /// Where decoded frames are written
+    #[arg(long, value_enum, default_value_t = SinkKind::V4l2)]
+    sink: SinkKind,
+
+    /// Output file for `--sink file`; the container is picked from the extension
+    #[arg(long, required_if_eq("sink", "file"))]
+    output_file: Option<String>,
+
+    /// Also save each incoming stream, as received, to this file (or to a
+    /// timestamped file per connection when it is a directory). Later
+    /// connections are numbered rather than overwriting it
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// What the virtual camera shows while no phone is connected: last, black,
+    /// color:RRGGBB, image:PATH (PNG/JPEG) or text:MESSAGE
+    #[arg(long, visible_alias = "hold", default_value_t = IdleSource::LastFrame)]
+    idle: IdleSource,
+
+    /// Pixel format written to the virtual camera: yuyv422, nv12, yuv420p,
+    /// rgb24, bgr24, mjpeg or mjpeg:Q (Q = 2-31 quantizer, lower is better)
+    #[arg(long, default_value_t = OutputFormat::default())]
+    output_format: OutputFormat,
+
+    /// Output frame rate of the virtual camera
+    #[arg(long, default_value = "30")]
+    fps: u16,
+
+    /// How the phone's picture is fitted into the output size
+    #[arg(long, value_enum, default_value_t = ScaleMode::Fit)]
+    scale_mode: ScaleMode,
+
+    /// Interpolation used when resizing
+    #[arg(long, value_enum, default_value_t = ScaleAlgorithm::Bilinear)]
+    scale_algorithm: ScaleAlgorithm,
+
+    /// Color of the bars added by `--scale-mode fit`, as RRGGBB
+    #[arg(long, value_parser = parse_hex_color, default_value = "000000")]
+    bar_color: [u8; 3],
+
+    /// Rotate incoming frames clockwise: 0, 90, 180 or 270
+    #[arg(long, default_value_t = Rotation::R0)]
+    rotate: Rotation,
+
+    /// Mirror incoming frames horizontally (before rotating)
+    #[arg(long)]
+    hflip: bool,
+
+    /// Mirror incoming frames vertically (before rotating)
+    #[arg(long)]
+    vflip: bool,
+
+    /// Ignore the rotation the sender stores in the stream (display matrix)
+    #[arg(long)]
+    no_autorotate: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SinkKind {
+    /// v4l2loopback device at `--device-path`
+    V4l2,
+    /// Regular video file at `--output-file`
+    File,
+    /// Decode only, discard the frames (no kernel module needed)
+    Null,
+}
+
+impl Args {
+    fn build_sink(&self) -> Box<dyn FrameSink> {
+        match self.sink {
+            SinkKind::V4l2 => Box::new(V4l2Sink::new(self.device_path.as_str())),
+            SinkKind::File => Box::new(FileSink::new(
+                self.output_file.as_deref().unwrap_or("output.mkv"),
+            )),
+            SinkKind::Null => Box::new(NullSink::new()),
+        }
+    }
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
     println!("🎥 Video Receiver CLI");
-    println!("📡 Listening on {}:{}", args.host, args.port);
+
+    let psk = args
+        .psk
+        .clone()
+        .map(PreSharedKey::new)
+        .transpose()
+        .context("Invalid --psk")?;
+    let tls = if args.tls {
+        let dir = args
+            .tls_dir
+            .clone()
+            .unwrap_or_else(TlsIdentity::default_dir);
+        Some(TlsIdentity::load_or_create(&dir)?)
+    } else {
+        None
+    };
+    let listener = bind_listener(&args.host, args.port)?;
+    let local_addr = listener.local_addr()?;
+    println!("📡 Listening on {local_addr}");
+    match &tls {
+        Some(tls) => println!(
+            "🔒 TLS certificate fingerprint (SHA-256): {}",
+            tls.fingerprint()
+        ),
+        None => println!("⚠️  No --tls: the stream crosses the network unencrypted"),
+    }
+    if psk.is_none() {
+        println!("⚠️  No --psk set: anyone who can reach this port can stream to the camera");
+    }
+    let pairing = Pairing {
+        version: PROTOCOL_VERSION,
+        addresses: pairing::reachable_addresses(local_addr.ip()),
+        port: local_addr.port(),
+        psk: args.psk.clone(),
+        fingerprint: tls.as_ref().map(TlsIdentity::fingerprint),
+    };
+    show_pairing(&args, &pairing)?;
+    let receiver_info = receiver_info(&args, &pairing);
+    // Answer on their own threads for as long as they're kept
+    let _mdns = if args.no_mdns {
+        None
+    } else {
+        advertise(&receiver_info)
+    };
+    let _beacons = if args.beacon {
+        let beacons = Beacons::spawn(receiver_info, args.beacon_port, psk.clone())?;
+        println!("📣 Broadcasting beacons to UDP port {}", args.beacon_port);
+        Some(beacons)
+    } else {
+        None
+    };
 
     // Initialize FFmpeg
     ffmpeg::init()?;
 
     println!("✅ Server started, waiting for connections...");
-    let receiver = VideoReceiver::new(args.width, args.height);
+    if let Some(record) = &args.record {
+        println!("💾 Recording incoming streams to {}", record.display());
+    }
+
+    // Create the display once; it stays open across connections and is prepared
+    // from each input stream's timing as senders come and go.
+    let mut display = VideoVirtualCamera::with_sink(args.width, args.height, args.build_sink())?;
+    display.set_output_format(args.output_format)?;
+    display.set_scale_mode(args.scale_mode);
+    display.set_scale_algorithm(args.scale_algorithm);
+    display.set_bar_color(args.bar_color);
+    display.set_transform(Transform {
+        rotation: args.rotate,
+        hflip: args.hflip,
+        vflip: args.vflip,
+    });
+    display.set_autorotate(!args.no_autorotate);
+    display.set_idle_source(args.idle.clone());
+    display.set_frame_rate(Rational::new(i32::from(args.fps.max(1)), 1));
+    // Open right away so apps see the idle source while we wait for the first sender
+    display.open()?;
+    println!(
+        "VideoDisplay initialized, showing {} until a sender connects",
+        args.idle
+    );
+
+    let camera: SharedCamera = Arc::new(Mutex::new(display));
+    let _keep_alive = KeepAlive::spawn(Arc::clone(&camera));
+    spawn_console(Arc::clone(&camera));
+
+    let receiver = VideoReceiver {
+        tls,
+        psk,
+        record: args.record.clone(),
+        width: args.width,
+        height: args.height,
+        fps: args.fps,
+        autorotate: !args.no_autorotate,
+    };
+    loop {
+        if let Err(err) = receiver.handle_connection(&camera, &listener) {
+            match err.downcast_ref::<ReceiverError>() {
+                Some(ReceiverError::Disconnected { peer }) => {
+                    println!("📴 Lost the connection to {peer}");
+                }
+                Some(ReceiverError::Accept(_)) => {
+                    eprintln!("Connection failed: {err:#}");
+                    // Don't spin if the failure is persistent (e.g. the address went away)
+                    std::thread::sleep(Duration::from_secs(1));
+                }
+                // A stray or refused client mustn't keep the real sender waiting
+                _ => eprintln!("Connection failed: {err:#}"),
+            }
+        }
+        lock_camera(&camera)?.end_stream();
+        println!("⏸️  Sender disconnected, holding the virtual camera open...");
+    }
+}
+
+/// Hands every frame the decoder has ready to the camera. Bad frames are
+/// skipped; a failing output ends the connection since nothing can be shown anyway.
+fn show_decoded_frames(
+    decoder: &mut ffmpeg::decoder::Video,
+    frame: &mut ffmpeg::frame::Video,
+    camera: &SharedCamera,
+) -> Result<()> {
     loop {
-        receiver.handle_connection(args.device_path.as_str(), args.port)?;
+        match decoder.receive_frame(frame) {
+            Ok(()) => match lock_camera(camera)?.show_frame(frame) {
+                Ok(()) => {}
+                Err(err @ (ReceiverError::Sink(_) | ReceiverError::Config(_))) => {
+                    return Err(err.into());
+                }
+                Err(err) => eprintln!("Error displaying/encoding frame: {err}"),
+            },
+            // EAGAIN: no more frames right now; EOF: fully drained
+            Err(err) if is_benign(&err) => return Ok(()),
+            Err(err) => {
+                eprintln!("Error receiving frame: {}", ReceiverError::Decode(err));
+                return Ok(());
+            }
+        }
     }
 }
 
+fn lock_camera(camera: &SharedCamera) -> Result<MutexGuard<'_, VideoVirtualCamera>> {
+    camera
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Virtual camera state poisoned by a panic"))
+}
+
+/// Reads orientation commands from stdin so the picture can be fixed without
+/// restarting: `rotate 0|90|180|270`, `hflip`, `vflip` (toggles) and `reset`.
+fn spawn_console(camera: SharedCamera) {
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            let mut words = line.split_whitespace();
+            let Some(command) = words.next() else {
+                continue;
+            };
+
+            let Ok(mut camera) = lock_camera(&camera) else {
+                break;
+            };
+            let mut transform = camera.transform();
+            match (command, words.next()) {
+                ("rotate", Some(degrees)) => match degrees.parse() {
+                    Ok(rotation) => transform.rotation = rotation,
+                    Err(err) => {
+                        eprintln!("{err}");
+                        continue;
+                    }
+                },
+                ("hflip", None) => transform.hflip = !transform.hflip,
+                ("vflip", None) => transform.vflip = !transform.vflip,
+                ("reset", None) => transform = Transform::default(),
+                _ => {
+                    eprintln!("Commands: rotate 0|90|180|270, hflip, vflip, reset");
+                    continue;
+                }
+            }
+            camera.set_transform(transform);
+            println!("🔃 Orientation: {transform}");
+        }
+    });
+}
+
+/// Prints the pairing QR code for the phone to scan and, with `--qr-png`,
+/// saves it.
+fn show_pairing(args: &Args, pairing: &Pairing) -> Result<()> {
+    if pairing.addresses.is_empty() {
+        println!("⚠️  No network address found for the pairing code, enter the address by hand");
+        return Ok(());
+    }
+    if !args.no_qr {
+        match pairing::render_terminal(pairing) {
+            Ok(code) => println!("📷 Scan to pair:\n{code}"),
+            // Not worth refusing to start over, the address can be typed in
+            Err(err) => eprintln!("Error drawing the pairing code: {err:#}"),
+        }
+    }
+    if let Some(path) = &args.qr_png {
+        pairing::save_png(pairing, path)?;
+        println!("📷 Pairing code saved to {}", path.display());
+    }
+    Ok(())
+}
+
+/// How the receiver describes itself to phones looking for one.
+fn receiver_info(args: &Args, pairing: &Pairing) -> ReceiverInfo {
+    ReceiverInfo {
+        name: args.name.clone().unwrap_or_else(host_name),
+        addresses: pairing.addresses.clone(),
+        port: pairing.port,
+        version: PROTOCOL_VERSION,
+        device_path: match args.sink {
+            SinkKind::V4l2 => args.device_path.clone(),
+            SinkKind::File | SinkKind::Null => String::new(),
+        },
+        width: args.width,
+        height: args.height,
+        tls: args.tls,
+    }
+}
+
+/// Announces the receiver to phones browsing for one. Not being found only
+/// means the address has to be typed in, so failures are just reported.
+fn advertise(receiver: &ReceiverInfo) -> Option<ServiceDaemon> {
+    let daemon = match ServiceDaemon::new() {
+        Ok(daemon) => daemon,
+        Err(err) => {
+            eprintln!("Error starting mDNS: {err}");
+            return None;
+        }
+    };
+    match mdns::advertise(&daemon, receiver) {
+        Ok(()) => {
+            println!(
+                "📣 Advertised as \"{}\" ({})",
+                receiver.name,
+                mdns::SERVICE_TYPE
+            );
+            Some(daemon)
+        }
+        Err(err) => {
+            eprintln!("Error advertising the receiver: {err}");
+            let _ = daemon.shutdown();
+            None
+        }
+    }
+}
+
+fn host_name() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|name| name.trim().to_string())
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "video-receiver".to_string())
+}
+
+/// Resolves `host`/`port` to a single socket address and binds it, so a typo
+/// or a foreign interface address fails right away with a readable message.
+fn bind_listener(host: &str, port: u16) -> Result<TcpListener> {
+    // Accept the bracketed IPv6 form people copy from URLs, e.g. "[::1]"
+    let host = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host);
+
+    let addr = (host, port)
+        .to_socket_addrs()
+        .with_context(|| format!("Invalid bind address '{host}'"))?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Bind address '{host}' did not resolve to any address"))?;
+
+    TcpListener::bind(addr).with_context(|| format!("Cannot bind to {addr}"))
+}
+
 struct VideoReceiver {
+    /// Senders must connect over TLS when set
+    tls: Option<TlsIdentity>,
+    /// Senders that can't answer a challenge with this key are refused
+    psk: Option<PreSharedKey>,
+    record: Option<PathBuf>,
+    /// Output settings, reported back to senders in the handshake
     width: u32,
     height: u32,
+    fps: u16,
+    autorotate: bool,
 }
 
 impl VideoReceiver {
-    fn new(width: u32, height: u32) -> Self {
-        Self { width, height }
+    /// Answer to a sender's hello: what it will get, or why it is refused.
+    fn acknowledge(&self, hello: &Hello) -> HelloAck {
+        let refusal = (hello.version != PROTOCOL_VERSION).then(|| {
+            format!(
+                "protocol version {} is not supported, this receiver speaks {PROTOCOL_VERSION}",
+                hello.version
+            )
+        });
+        HelloAck {
+            version: PROTOCOL_VERSION,
+            accepted: refusal.is_none(),
+            reason: refusal,
+            params: StreamParams {
+                codec: hello.params.codec.clone(),
+                container: hello.params.container.clone(),
+                width: self.width,
+                height: self.height,
+                fps: f64::from(self.fps),
+                orientation: if self.autorotate {
+                    hello.params.orientation
+                } else {
+                    0
+                },
+            },
+        }
     }
 
-    fn handle_connection(&self, device_path: &str, port: u16) -> Result<()> {
-        println!("🎬 Starting video playback...");
-
-        // Create the display; it will later be prepared using the input stream timing.
-        let mut display = VideoVirtualCamera::new(self.width, self.height, device_path)?;
-        display.use_rawvideo_yuyv422();
-        println!("VideoDisplay initialized");
-
-        // Open a listening TCP input. You can also put listen=1 in the URL query, but this works:
-        let mut dict = Dictionary::new();
-        dict.set("listen", "1");
+    fn handle_connection(&self, camera: &SharedCamera, listener: &TcpListener) -> Result<()> {
+        let ingest = Ingest::accept(
+            listener,
+            self.tls.as_ref().map(TlsIdentity::config),
+            self.psk.as_ref(),
+            |hello| self.acknowledge(hello),
+        )?;
+        match ingest.hello() {
+            Some(hello) => println!(
+                "📱 {} ({}): {} in {}, {}x{} @ {} fps, rotated {}°",
+                hello.device_name,
+                ingest.peer(),
+                hello.params.codec,
+                hello.params.container,
+                hello.params.width,
+                hello.params.height,
+                hello.params.fps,
+                hello.params.orientation,
+            ),
+            None => println!("📱 Legacy raw stream from {}", ingest.peer()),
+        }
+        // Used if the stream has no display matrix of its own
+        let announced = ingest
+            .hello()
+            .filter(|hello| hello.params.orientation != 0)
+            .map(|hello| Transform {
+                rotation: Rotation::from_degrees(f64::from(hello.params.orientation)),
+                ..Transform::default()
+            });
+        lock_camera(camera)?.set_sender_orientation(announced);
 
-        let url = format!("tcp://{}:{}", "0.0.0.0", port);
-        let mut ictx = ffmpeg::format::input_with_dictionary(&url, dict)?;
-        println!("Input context opened: {}", url);
+        println!("🎬 Starting video playback...");
+        // Declared after `ingest` so it is closed first
+        let mut ictx = ingest.open_input()?;
+        println!("Input context opened for {}", ingest.peer());
 
         // Find the first video stream, create a decoder from its parameters,
         // and prepare the display from that stream (parameters + timebase + framerate).
         let mut video_stream_index: Option<usize> = None;
         let mut decoder_opt = None;
+        let mut recorder: Option<StreamRecorder> = None;
 
         for stream in ictx.streams() {
             if stream.parameters().medium() == ffmpeg::media::Type::Video {
@@ -82,12 +539,25 @@ impl VideoReceiver {
 
                 // Prepare the display using the discovered stream timing/parameters.
                 // This should set encoder/output time_base and frame rate inside VideoDisplay.
-                display.prepare_from_stream(&stream);
+                lock_camera(camera)?.prepare_from_stream(&stream);
 
                 // Create the decoder from the stream parameters
-                let ctx = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?;
-                let decoder = ctx.decoder().video()?;
+                let ctx = ffmpeg::codec::context::Context::from_parameters(stream.parameters())
+                    .map_err(ReceiverError::Decode)?;
+                let decoder = ctx.decoder().video().map_err(ReceiverError::Decode)?;
                 decoder_opt = Some(decoder);
+
+                // A failing recording shouldn't take the virtual camera down with it
+                if let Some(target) = &self.record {
+                    let path = StreamRecorder::session_path(target);
+                    match StreamRecorder::open(&path, &stream) {
+                        Ok(rec) => {
+                            println!("💾 Recording to {}", path.display());
+                            recorder = Some(rec);
+                        }
+                        Err(err) => eprintln!("Error starting recording: {err:#}"),
+                    }
+                }
                 println!(
                     "Using video stream index {} | time_base={}/{} | avg_frame_rate={}/{}",
                     stream.index(),
@@ -100,16 +570,12 @@ impl VideoReceiver {
             }
         }
 
-        let Some(video_stream_index) = video_stream_index else {
-            return Err(anyhow::anyhow!(
-                "Didn't find a video stream in the incoming connection"
-            ));
-        };
-
-        let Some(mut decoder) = decoder_opt else {
-            return Err(anyhow::anyhow!(
-                "Couldn't create a decoder for the incoming video stream"
-            ));
+        let (Some(video_stream_index), Some(mut decoder)) = (video_stream_index, decoder_opt)
+        else {
+            // No video stream in the incoming connection
+            return Err(ingest
+                .blame(ReceiverError::Demux(ffmpeg::Error::StreamNotFound))
+                .into());
         };
 
         println!("Decoder ready, starting demux/decode loop…");
@@ -123,73 +589,51 @@ impl VideoReceiver {
                 continue;
             }
 
-            // Send packet to decoder; ignore EAGAIN, report only real errors
-            if let Err(err) = decoder.send_packet(&packet) {
-                // EAGAIN means the decoder needs draining; we'll handle it by receive loop below
-                if err.to_string().contains("Resource temporarily unavailable") {
-                    // benign
-                } else {
-                    eprintln!("Error sending packet to decoder: {err}");
-                }
+            // Send packet to decoder; EAGAIN means it needs draining first, which the
+            // receive loop below does
+            if let Err(err) = decoder.send_packet(&packet)
+                && !is_again(&err)
+            {
+                eprintln!(
+                    "Error sending packet to decoder: {}",
+                    ReceiverError::Decode(err)
+                );
             }
 
-            // Receive and process all frames available after this packet
-            loop {
-                match decoder.receive_frame(&mut frame) {
-                    Ok(()) => {
-                        // Let the display handle timing/encoding/muxing.
-                        if let Err(err) = display.show_frame(&frame) {
-                            eprintln!("Error displaying/encoding frame: {err}");
-                        }
-                    }
-                    Err(err) => {
-                        // Break only on EAGAIN (no more frames right now) or EOF
-                        let msg = err.to_string();
-                        if msg.contains("Resource temporarily unavailable")
-                            || msg.contains("End of file")
-                        {
-                            break;
-                        } else {
-                            eprintln!("Error receiving frame: {err}");
-                            break;
-                        }
-                    }
-                }
+            if let Some(rec) = &mut recorder
+                && let Err(err) = rec.write_packet(packet)
+            {
+                eprintln!("Error recording packet, recording stopped: {err}");
+                recorder = None;
             }
+
+            // Receive and process all frames available after this packet
+            show_decoded_frames(&mut decoder, &mut frame, camera)?;
         }
 
         // Flush the decoder at end of stream and drain remaining frames
         if let Err(err) = decoder.send_eof() {
-            eprintln!("Error sending EOF to decoder: {err}");
+            eprintln!(
+                "Error sending EOF to decoder: {}",
+                ReceiverError::Decode(err)
+            );
         }
+        show_decoded_frames(&mut decoder, &mut frame, camera)?;
 
-        loop {
-            match decoder.receive_frame(&mut frame) {
-                Ok(()) => {
-                    if let Err(err) = display.show_frame(&frame) {
-                        eprintln!("Error displaying/encoding frame (drain): {err}");
-                    }
-                }
-                Err(err) => {
-                    // Stop draining on EAGAIN or EOF
-                    let msg = err.to_string();
-                    if msg.contains("Resource temporarily unavailable")
-                        || msg.contains("End of file")
-                    {
-                        break;
-                    } else {
-                        eprintln!("Error draining decoder: {err}");
-                        break;
-                    }
-                }
+        if let Some(mut rec) = recorder {
+            match rec.finish() {
+                Ok(()) => println!("💾 Saved recording {}", rec.path().display()),
+                Err(err) => eprintln!("Error finalizing recording: {err}"),
             }
         }
 
-        // Explicitly finish (also happens in Drop, but this forces trailer write now)
-        if let Err(err) = display.finish() {
-            eprintln!("Error finalizing output: {err}");
+        // The demuxer just stops at the end of its input, however it came about
+        if ingest.vanished() {
+            return Err(ReceiverError::Disconnected {
+                peer: ingest.peer(),
+            }
+            .into());
         }
-
         println!("✅ Finished receiving and writing video.");
         Ok(())
     }
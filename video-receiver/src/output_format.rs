@@ -0,0 +1,203 @@
+use std::fmt;
+use std::str::FromStr;
+
+use ffmpeg_next as ffmpeg;
+use ffmpeg_next::{codec::Id as CodecId, util::format::pixel::Pixel};
+
+use crate::error::ReceiverError;
+
+/// Range accepted for the MJPEG quantizer, as in FFmpeg's `-q:v` (lower is better).
+pub const MJPEG_QUALITY_RANGE: std::ops::RangeInclusive<u8> = 2..=31;
+
+/// Pixel format / codec written to the virtual camera.
+///
+/// Parsed from `yuyv422`, `nv12`, `yuv420p`, `rgb24`, `bgr24`, `mjpeg` or `mjpeg:Q`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Raw YUYV needs no encoder and is what most webcams offer
+    #[default]
+    Yuyv422,
+    Nv12,
+    Yuv420p,
+    Rgb24,
+    Bgr24,
+    /// Motion JPEG; `quality` is a fixed quantizer (see [`MJPEG_QUALITY_RANGE`]),
+    /// `None` leaves it to the encoder's rate control.
+    Mjpeg {
+        quality: Option<u8>,
+    },
+}
+
+impl OutputFormat {
+    pub fn codec_id(self) -> CodecId {
+        match self {
+            Self::Mjpeg { .. } => CodecId::MJPEG,
+            _ => CodecId::RAWVIDEO,
+        }
+    }
+
+    /// Pixel format of the frames handed to the encoder.
+    pub fn pixel_format(self) -> Pixel {
+        match self {
+            Self::Yuyv422 => Pixel::YUYV422,
+            Self::Nv12 => Pixel::NV12,
+            Self::Yuv420p => Pixel::YUV420P,
+            Self::Rgb24 => Pixel::RGB24,
+            Self::Bgr24 => Pixel::BGR24,
+            // JPEG is full range; the "yuvj" format makes swscale expand limited-range input
+            Self::Mjpeg { .. } => Pixel::YUVJ420P,
+        }
+    }
+
+    pub fn quality(self) -> Option<u8> {
+        match self {
+            Self::Mjpeg { quality } => quality,
+            _ => None,
+        }
+    }
+
+    pub fn is_full_range(self) -> bool {
+        matches!(self, Self::Mjpeg { .. })
+    }
+
+    /// Checks that the linked FFmpeg has the encoder and that it takes our pixel format.
+    /// Needs `ffmpeg::init()` to have run.
+    pub fn validate(self) -> Result<(), ReceiverError> {
+        let codec_id = self.codec_id();
+        let codec = ffmpeg::encoder::find(codec_id).ok_or_else(|| {
+            ReceiverError::Config(format!(
+                "Encoder not available in this FFmpeg: {codec_id:?}"
+            ))
+        })?;
+
+        // Encoders that don't list formats (rawvideo) accept any of them
+        if let Some(mut formats) = codec.video().map_err(ReceiverError::Encode)?.formats() {
+            let pixel_format = self.pixel_format();
+            if !formats.any(|f| f == pixel_format) {
+                return Err(ReceiverError::Config(format!(
+                    "Encoder {} doesn't support pixel format {pixel_format:?}",
+                    codec.name()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "yuyv422" | "yuyv" => Ok(Self::Yuyv422),
+            "nv12" => Ok(Self::Nv12),
+            "yuv420p" | "i420" => Ok(Self::Yuv420p),
+            "rgb24" => Ok(Self::Rgb24),
+            "bgr24" => Ok(Self::Bgr24),
+            "mjpeg" => Ok(Self::Mjpeg { quality: None }),
+            other => match other.strip_prefix("mjpeg:") {
+                Some(q) => {
+                    let quality = q
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|q| MJPEG_QUALITY_RANGE.contains(q))
+                        .ok_or_else(|| {
+                            format!(
+                                "invalid MJPEG quality '{q}', expected {}-{} (lower is better)",
+                                MJPEG_QUALITY_RANGE.start(),
+                                MJPEG_QUALITY_RANGE.end()
+                            )
+                        })?;
+                    Ok(Self::Mjpeg {
+                        quality: Some(quality),
+                    })
+                }
+                None => Err(format!(
+                    "invalid output format '{s}', expected yuyv422, nv12, yuv420p, rgb24, bgr24, mjpeg or mjpeg:Q"
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Yuyv422 => write!(f, "yuyv422"),
+            Self::Nv12 => write!(f, "nv12"),
+            Self::Yuv420p => write!(f, "yuv420p"),
+            Self::Rgb24 => write!(f, "rgb24"),
+            Self::Bgr24 => write!(f, "bgr24"),
+            Self::Mjpeg { quality: None } => write!(f, "mjpeg"),
+            Self::Mjpeg { quality: Some(q) } => write!(f, "mjpeg:{q}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_format() {
+        let cases = [
+            ("yuyv422", OutputFormat::Yuyv422),
+            ("YUYV", OutputFormat::Yuyv422),
+            ("nv12", OutputFormat::Nv12),
+            ("yuv420p", OutputFormat::Yuv420p),
+            ("i420", OutputFormat::Yuv420p),
+            ("rgb24", OutputFormat::Rgb24),
+            ("bgr24", OutputFormat::Bgr24),
+            ("mjpeg", OutputFormat::Mjpeg { quality: None }),
+            ("MJPEG:2", OutputFormat::Mjpeg { quality: Some(2) }),
+            ("mjpeg:31", OutputFormat::Mjpeg { quality: Some(31) }),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(input.parse::<OutputFormat>(), Ok(expected), "{input}");
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_formats_and_qualities() {
+        for input in [
+            "",
+            "h264",
+            "mjpeg:",
+            "mjpeg:1",
+            "mjpeg:32",
+            "mjpeg:high",
+            "yuyv422:5",
+        ] {
+            assert!(input.parse::<OutputFormat>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let formats = [
+            OutputFormat::Yuyv422,
+            OutputFormat::Nv12,
+            OutputFormat::Yuv420p,
+            OutputFormat::Rgb24,
+            OutputFormat::Bgr24,
+            OutputFormat::Mjpeg { quality: None },
+            OutputFormat::Mjpeg { quality: Some(5) },
+        ];
+        for format in formats {
+            assert_eq!(format.to_string().parse::<OutputFormat>(), Ok(format));
+        }
+    }
+
+    #[test]
+    fn only_mjpeg_is_encoded_and_full_range() {
+        assert_eq!(OutputFormat::default(), OutputFormat::Yuyv422);
+        assert_eq!(OutputFormat::Yuyv422.codec_id(), CodecId::RAWVIDEO);
+        assert!(!OutputFormat::Yuyv422.is_full_range());
+
+        let mjpeg = OutputFormat::Mjpeg { quality: Some(5) };
+        assert_eq!(mjpeg.codec_id(), CodecId::MJPEG);
+        assert_eq!(mjpeg.pixel_format(), Pixel::YUVJ420P);
+        assert_eq!(mjpeg.quality(), Some(5));
+        assert!(mjpeg.is_full_range());
+    }
+}
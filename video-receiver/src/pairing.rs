@@ -0,0 +1,73 @@
+use std::fs::{OpenOptions, Permissions};
+use std::io::{BufWriter, Write};
+use std::net::IpAddr;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use camstream_protocol::Pairing;
+use qrcode::QrCode;
+use qrcode::render::unicode::Dense1x2;
+
+/// Addresses a phone can reach a listener bound to `bound` at: that address,
+/// or for a wildcard bind every interface address of its family (IPv4 first
+/// for `::`, which takes both). Loopback and link-local addresses are left
+/// out, a phone can't use them.
+pub fn reachable_addresses(bound: IpAddr) -> Vec<IpAddr> {
+    if !bound.is_unspecified() {
+        return vec![bound];
+    }
+    let interfaces = match if_addrs::get_if_addrs() {
+        Ok(interfaces) => interfaces,
+        Err(err) => {
+            eprintln!("Error listing network interfaces: {err}");
+            return Vec::new();
+        }
+    };
+    let mut addresses: Vec<IpAddr> = interfaces
+        .iter()
+        .filter(|iface| !iface.is_loopback() && !iface.is_link_local())
+        .map(if_addrs::Interface::ip)
+        .filter(|ip| ip.is_ipv4() || bound.is_ipv6())
+        .collect();
+    addresses.sort_by_key(IpAddr::is_ipv6);
+    addresses.dedup();
+    addresses
+}
+
+/// The pairing code as a QR code drawn with half-block characters.
+pub fn render_terminal(pairing: &Pairing) -> Result<String> {
+    let code = QrCode::new(pairing.to_string()).context("Pairing code too long for a QR code")?;
+    // Inverted: terminals are usually light text on a dark background
+    Ok(code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build())
+}
+
+/// Writes the pairing code as a QR code image, for showing it on another screen.
+/// Only the owner can read it, as it may carry the pre-shared key.
+pub fn save_png(pairing: &Pairing, path: &Path) -> Result<()> {
+    let code = QrCode::new(pairing.to_string()).context("Pairing code too long for a QR code")?;
+    let image = code
+        .render::<image::Luma<u8>>()
+        .min_dimensions(256, 256)
+        .build();
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("Couldn't create {}", path.display()))?;
+    // The mode only applies to new files, an older image may be readable by anyone
+    file.set_permissions(Permissions::from_mode(0o600))
+        .with_context(|| format!("Couldn't restrict access to {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    image
+        .write_to(&mut out, image::ImageFormat::Png)
+        .with_context(|| format!("Couldn't write {}", path.display()))?;
+    out.flush()
+        .with_context(|| format!("Couldn't write {}", path.display()))
+}
@@ -0,0 +1,185 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use ffmpeg_next::{Packet, Rational, codec, encoder, format};
+
+/// Container used when `--record` points at a directory; Matroska takes any codec the phone sends.
+const DEFAULT_EXTENSION: &str = "mkv";
+
+/// Remuxes the packets of one incoming video stream into a file, without re-encoding.
+pub struct StreamRecorder {
+    output_context: format::context::Output,
+    in_time_base: Rational,
+    out_stream_index: usize,
+    path: PathBuf,
+    finished: bool,
+}
+
+impl StreamRecorder {
+    /// Resolves the file to write for a new connection: a directory gets a
+    /// timestamped file per session, anything else is used as-is the first
+    /// time. An existing file is never overwritten, later sessions get a
+    /// numbered name instead (`stream-1.mkv`, `stream-2.mkv`...).
+    pub fn session_path(target: &Path) -> PathBuf {
+        let path = if target.is_dir() {
+            let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f");
+            target.join(format!("session-{stamp}.{DEFAULT_EXTENSION}"))
+        } else {
+            target.to_path_buf()
+        };
+        unused_path(path)
+    }
+
+    /// Creates `path` (format guessed from its extension) with one stream
+    /// mirroring `in_stream`'s codec parameters.
+    pub fn open(path: &Path, in_stream: &format::stream::Stream) -> Result<Self> {
+        let mut output = format::output(path)
+            .with_context(|| format!("Couldn't create recording {}", path.display()))?;
+
+        let out_stream_index;
+        {
+            let mut ost = output.add_stream(encoder::find(codec::Id::None))?;
+            ost.set_parameters(in_stream.parameters());
+            // The WebM codec tag means nothing to mp4/mkv; let the muxer pick its own
+            let mut parameters = ost.parameters();
+            // SAFETY: `parameters` wraps the new stream's `codecpar`, allocated by
+            // `add_stream` and owned by `output`, which `ost` borrows mutably: the
+            // pointer is valid and nothing else touches it during the write. The
+            // wrapper has an owner, so dropping it doesn't free the parameters.
+            unsafe {
+                (*parameters.as_mut_ptr()).codec_tag = 0;
+            }
+            out_stream_index = ost.index();
+        }
+
+        output.write_header()?;
+
+        Ok(Self {
+            output_context: output,
+            in_time_base: in_stream.time_base(),
+            out_stream_index,
+            path: path.to_path_buf(),
+            finished: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write_packet(&mut self, mut packet: Packet) -> Result<()> {
+        let out_tb = self
+            .output_context
+            .stream(self.out_stream_index)
+            .ok_or_else(|| anyhow::anyhow!("Recording stream {} vanished", self.out_stream_index))?
+            .time_base();
+
+        packet.rescale_ts(self.in_time_base, out_tb);
+        packet.set_position(-1);
+        packet.set_stream(self.out_stream_index);
+        packet.write_interleaved(&mut self.output_context)?;
+        Ok(())
+    }
+
+    /// Writes the trailer (moov atom / cues) so the file is playable.
+    pub fn finish(&mut self) -> Result<()> {
+        if !self.finished {
+            self.finished = true;
+            self.output_context.write_trailer()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for StreamRecorder {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            eprintln!("Error finalizing recording {}: {err}", self.path.display());
+        }
+    }
+}
+
+/// `path` if it doesn't exist yet, or else the first free `stem-N.extension` next to it.
+fn unused_path(path: PathBuf) -> PathBuf {
+    if !path.exists() {
+        return path;
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    let mut n = 1u32;
+    loop {
+        let candidate = path.with_file_name(format!("{stem}-{n}{extension}"));
+        if !candidate.exists() {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Fresh empty directory under the system temp dir, unique to the test.
+    fn scratch_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("receiver-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn existing_files_get_a_numbered_name() {
+        let dir = scratch_dir("unused-path");
+        let target = dir.join("stream.mkv");
+        assert_eq!(StreamRecorder::session_path(&target), target);
+
+        fs::write(&target, b"").unwrap();
+        assert_eq!(
+            StreamRecorder::session_path(&target),
+            dir.join("stream-1.mkv")
+        );
+        fs::write(dir.join("stream-1.mkv"), b"").unwrap();
+        assert_eq!(
+            StreamRecorder::session_path(&target),
+            dir.join("stream-2.mkv")
+        );
+
+        let bare = dir.join("stream");
+        fs::write(&bare, b"").unwrap();
+        assert_eq!(unused_path(bare), dir.join("stream-1"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_directory_gets_one_file_per_session() {
+        let dir = scratch_dir("session-path");
+        let first = StreamRecorder::session_path(&dir);
+        assert_eq!(first.parent(), Some(dir.as_path()));
+        assert_eq!(first.extension().unwrap(), DEFAULT_EXTENSION);
+        assert!(
+            first
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with("session-")
+        );
+
+        // Even within the same millisecond the next session gets another file
+        fs::write(&first, b"").unwrap();
+        let second = StreamRecorder::session_path(&dir);
+        assert_ne!(second, first);
+        assert!(!second.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,481 @@
+use std::ffi::c_int;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+
+use ffmpeg_next::{
+    Error, ffi, frame,
+    software::scaling::{context::Context as Scaler, flag::Flags as ScaleFlags},
+    util::{color::Range as ColorRange, format::pixel::Pixel},
+};
+
+/// Working format for letterboxing/cropping: planar with full-size chroma,
+/// so any pixel offset can be copied without caring about subsampling.
+const CANVAS_PIX_FMT: Pixel = Pixel::YUV444P;
+
+/// How the incoming picture is fitted into the output size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ScaleMode {
+    /// Keep the aspect ratio and pad with bars (letterbox/pillarbox)
+    #[default]
+    Fit,
+    /// Keep the aspect ratio and crop the overflow, centered
+    Fill,
+    /// Scale each axis independently, distorting the picture
+    Stretch,
+}
+
+/// swscale interpolation used for resizing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ScaleAlgorithm {
+    #[default]
+    Bilinear,
+    Bicubic,
+    Lanczos,
+    /// Averaging; the sharpest choice for large downscales
+    Area,
+}
+
+impl ScaleAlgorithm {
+    fn flags(self) -> ScaleFlags {
+        match self {
+            Self::Bilinear => ScaleFlags::BILINEAR,
+            Self::Bicubic => ScaleFlags::BICUBIC,
+            Self::Lanczos => ScaleFlags::LANCZOS,
+            Self::Area => ScaleFlags::AREA,
+        }
+    }
+}
+
+/// swscale contexts have no thread affinity, ffmpeg-next just doesn't mark them `Send`.
+pub(crate) struct SendScaler(pub(crate) Scaler);
+
+// SAFETY: the context is only ever used through `&mut`, i.e. from one thread at a time.
+unsafe impl Send for SendScaler {}
+
+impl Deref for SendScaler {
+    type Target = Scaler;
+
+    fn deref(&self) -> &Scaler {
+        &self.0
+    }
+}
+
+impl DerefMut for SendScaler {
+    fn deref_mut(&mut self) -> &mut Scaler {
+        &mut self.0
+    }
+}
+
+/// Size and pixel format of the frames coming into the scaler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SourceFormat {
+    width: u32,
+    height: u32,
+    format: Pixel,
+    full_range: bool,
+}
+
+impl SourceFormat {
+    fn of(frame: &frame::Video) -> Self {
+        Self {
+            width: frame.width(),
+            height: frame.height(),
+            format: frame.format(),
+            full_range: frame.color_range() == ColorRange::JPEG,
+        }
+    }
+}
+
+impl fmt::Display for SourceFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{} {:?}", self.width, self.height, self.format)?;
+        if self.full_range {
+            f.write_str(" (full range)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the resized picture lands on the output canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Placement {
+    // Size the source is resized to
+    scaled_w: u32,
+    scaled_h: u32,
+    // Top-left corner of the copied region, in the resized picture and on the canvas
+    src_x: u32,
+    src_y: u32,
+    dst_x: u32,
+    dst_y: u32,
+    // Size of the copied region
+    copy_w: u32,
+    copy_h: u32,
+}
+
+impl Placement {
+    /// Layout for `mode`; [`ScaleMode::Stretch`] always covers the whole output.
+    fn new(mode: ScaleMode, src_w: u32, src_h: u32, out_w: u32, out_h: u32) -> Self {
+        if mode == ScaleMode::Stretch {
+            return Self {
+                scaled_w: out_w,
+                scaled_h: out_h,
+                src_x: 0,
+                src_y: 0,
+                dst_x: 0,
+                dst_y: 0,
+                copy_w: out_w,
+                copy_h: out_h,
+            };
+        }
+
+        let (sw, sh) = (u64::from(src_w.max(1)), u64::from(src_h.max(1)));
+        let (ow, oh) = (u64::from(out_w), u64::from(out_h));
+
+        // Is the source wider (relative to its height) than the output?
+        // Fit then matches the output width and leaves bars above/below, Fill matches the height
+        let source_wider = sw * oh > sh * ow;
+        let match_width = (mode == ScaleMode::Fill) != source_wider;
+
+        let (scaled_w, scaled_h) = if match_width {
+            (out_w, ((ow * sh + sw / 2) / sw).max(1) as u32)
+        } else {
+            (((oh * sw + sh / 2) / sh).max(1) as u32, out_h)
+        };
+
+        let copy_w = scaled_w.min(out_w);
+        let copy_h = scaled_h.min(out_h);
+        Self {
+            scaled_w,
+            scaled_h,
+            src_x: (scaled_w - copy_w) / 2,
+            src_y: (scaled_h - copy_h) / 2,
+            dst_x: (out_w - copy_w) / 2,
+            dst_y: (out_h - copy_h) / 2,
+            copy_w,
+            copy_h,
+        }
+    }
+}
+
+/// Converts decoded frames to the output size and pixel format according to a [`ScaleMode`].
+///
+/// `Stretch` is a single swscale pass. `Fit` and `Fill` resize into a
+/// [`CANVAS_PIX_FMT`] picture, copy the visible part onto a canvas holding the
+/// bars, then convert the canvas to the output format.
+pub struct FrameScaler {
+    mode: ScaleMode,
+    algorithm: ScaleAlgorithm,
+    bar_color: [u8; 3],
+
+    out_w: u32,
+    out_h: u32,
+    out_fmt: Pixel,
+    out_full_range: bool,
+
+    source: Option<SourceFormat>, // what the contexts below were built for
+    placement: Option<Placement>,
+    resize: Option<SendScaler>, // source -> resized (or straight to output when stretching)
+    finish: Option<SendScaler>, // canvas -> output format
+    resized: Option<frame::Video>, // CANVAS_PIX_FMT, placement.scaled_w × scaled_h
+    canvas: Option<frame::Video>, // CANVAS_PIX_FMT, output size, bars pre-painted
+}
+
+impl FrameScaler {
+    /// `out_full_range` selects full-range (JPEG) output for YUV formats that
+    /// don't imply it.
+    pub fn new(out_w: u32, out_h: u32, out_fmt: Pixel, out_full_range: bool) -> Self {
+        Self {
+            mode: ScaleMode::default(),
+            algorithm: ScaleAlgorithm::default(),
+            bar_color: [0, 0, 0],
+            out_w,
+            out_h,
+            out_fmt,
+            out_full_range,
+            source: None,
+            placement: None,
+            resize: None,
+            finish: None,
+            resized: None,
+            canvas: None,
+        }
+    }
+
+    /// A scaler with the same output and settings, without the swscale contexts.
+    pub fn with_same_settings(&self) -> Self {
+        Self {
+            mode: self.mode,
+            algorithm: self.algorithm,
+            bar_color: self.bar_color,
+            ..Self::new(self.out_w, self.out_h, self.out_fmt, self.out_full_range)
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: ScaleMode) {
+        self.mode = mode;
+        self.reset();
+    }
+
+    pub fn set_algorithm(&mut self, algorithm: ScaleAlgorithm) {
+        self.algorithm = algorithm;
+        self.reset();
+    }
+
+    /// Color of the letterbox/pillarbox bars in [`ScaleMode::Fit`], as RGB.
+    pub fn set_bar_color(&mut self, rgb: [u8; 3]) {
+        self.bar_color = rgb;
+        self.reset();
+    }
+
+    pub fn set_output_format(&mut self, out_fmt: Pixel, full_range: bool) {
+        self.out_fmt = out_fmt;
+        self.out_full_range = full_range;
+        self.reset();
+    }
+
+    /// Drops the swscale contexts; they are rebuilt for the next frame.
+    pub fn reset(&mut self) {
+        self.source = None;
+        self.placement = None;
+        self.resize = None;
+        self.finish = None;
+        self.resized = None;
+        self.canvas = None;
+    }
+
+    /// Scales `src` into `dst`, which must be empty or already have the output size/format.
+    pub fn run(&mut self, src: &frame::Video, dst: &mut frame::Video) -> Result<(), Error> {
+        // Senders may change size (phone rotated) or format mid-stream; the
+        // output size stays the same, only the contexts are rebuilt
+        let source = SourceFormat::of(src);
+        if self.source != Some(source) {
+            if let Some(previous) = self.source {
+                eprintln!("🔄 Input changed from {previous} to {source}, rebuilding scaler");
+            }
+            self.build(source)?;
+        }
+
+        // `build` always sets it
+        let Some(resize) = self.resize.as_mut() else {
+            return Err(Error::Bug);
+        };
+        let (Some(placement), Some(finish), Some(resized), Some(canvas)) = (
+            self.placement,
+            self.finish.as_mut(),
+            self.resized.as_mut(),
+            self.canvas.as_mut(),
+        ) else {
+            // Stretch: straight to the output
+            resize.run(src, dst)?;
+            return Ok(());
+        };
+
+        resize.run(src, resized)?;
+        copy_region(resized, canvas, placement);
+        finish.run(canvas, dst)?;
+        Ok(())
+    }
+
+    fn build(&mut self, source: SourceFormat) -> Result<(), Error> {
+        self.reset();
+        let SourceFormat {
+            width: src_w,
+            height: src_h,
+            format: src_fmt,
+            full_range: src_full_range,
+        } = source;
+        let flags = self.algorithm.flags();
+
+        if self.mode == ScaleMode::Stretch {
+            let mut resize = Scaler::get(
+                src_fmt,
+                src_w,
+                src_h,
+                self.out_fmt,
+                self.out_w,
+                self.out_h,
+                flags,
+            )?;
+            set_ranges(&mut resize, src_full_range, self.out_full_range);
+            self.resize = Some(SendScaler(resize));
+            self.source = Some(source);
+            return Ok(());
+        }
+
+        let placement = Placement::new(self.mode, src_w, src_h, self.out_w, self.out_h);
+        // The canvas is limited range, like its bars
+        let mut resize = Scaler::get(
+            src_fmt,
+            src_w,
+            src_h,
+            CANVAS_PIX_FMT,
+            placement.scaled_w,
+            placement.scaled_h,
+            flags,
+        )?;
+        set_ranges(&mut resize, src_full_range, false);
+        self.resize = Some(SendScaler(resize));
+        // Same size, format conversion only
+        let mut finish = Scaler::get(
+            CANVAS_PIX_FMT,
+            self.out_w,
+            self.out_h,
+            self.out_fmt,
+            self.out_w,
+            self.out_h,
+            flags,
+        )?;
+        set_ranges(&mut finish, false, self.out_full_range);
+        self.finish = Some(SendScaler(finish));
+        self.resized = Some(frame::Video::new(
+            CANVAS_PIX_FMT,
+            placement.scaled_w,
+            placement.scaled_h,
+        ));
+        self.canvas = Some(filled_canvas(self.out_w, self.out_h, self.bar_color));
+        self.placement = Some(placement);
+        self.source = Some(source);
+        Ok(())
+    }
+}
+
+/// Tells swscale whether the source and destination are full range. It
+/// infers full range from the `YUVJ` formats only, which is kept; RGB is
+/// always full range and ignores this.
+fn set_ranges(scaler: &mut Scaler, src_full: bool, dst_full: bool) {
+    let mut inv_table: *mut c_int = ptr::null_mut();
+    let mut table: *mut c_int = ptr::null_mut();
+    let (mut src_range, mut dst_range) = (0, 0);
+    let (mut brightness, mut contrast, mut saturation) = (0, 0, 0);
+    // SAFETY: `scaler` is borrowed mutably, so the context pointer is valid and
+    // nothing else uses the context during these calls. The tables handed back
+    // point into the context itself, which copies them over in place.
+    unsafe {
+        let context = scaler.as_mut_ptr();
+        if ffi::sws_getColorspaceDetails(
+            context,
+            &mut inv_table,
+            &mut src_range,
+            &mut table,
+            &mut dst_range,
+            &mut brightness,
+            &mut contrast,
+            &mut saturation,
+        ) < 0
+        {
+            return;
+        }
+        // Fails for conversions without adjustable ranges, which is fine
+        ffi::sws_setColorspaceDetails(
+            context,
+            inv_table,
+            src_range | c_int::from(src_full),
+            table,
+            dst_range | c_int::from(dst_full),
+            brightness,
+            contrast,
+            saturation,
+        );
+    }
+}
+
+/// Canvas painted with `rgb`, converted to limited-range BT.601 like the resized source.
+fn filled_canvas(width: u32, height: u32, rgb: [u8; 3]) -> frame::Video {
+    let [r, g, b] = rgb.map(f32::from);
+    let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+    let cb = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+    let cr = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+
+    let mut canvas = frame::Video::new(CANVAS_PIX_FMT, width, height);
+    for (plane, value) in [y, cb, cr].into_iter().enumerate() {
+        canvas
+            .data_mut(plane)
+            .fill(value.round().clamp(0.0, 255.0) as u8);
+    }
+    canvas
+}
+
+/// Copies the visible region of the resized picture onto the canvas, plane by plane.
+fn copy_region(src: &frame::Video, dst: &mut frame::Video, p: Placement) {
+    let (copy_w, copy_h) = (p.copy_w as usize, p.copy_h as usize);
+    for plane in 0..src.planes() {
+        let src_stride = src.stride(plane);
+        let dst_stride = dst.stride(plane);
+        let src_data = src.data(plane);
+        let dst_data = dst.data_mut(plane);
+        for row in 0..copy_h {
+            let s = (p.src_y as usize + row) * src_stride + p.src_x as usize;
+            let d = (p.dst_y as usize + row) * dst_stride + p.dst_x as usize;
+            dst_data[d..d + copy_w].copy_from_slice(&src_data[s..s + copy_w]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placement_letterboxes_pillarboxes_crops_and_stretches() {
+        // Into 640x480: mode, source size, then
+        // [scaled_w, scaled_h, src_x, src_y, dst_x, dst_y, copy_w, copy_h]
+        let cases = [
+            // Wide source: bars above and below
+            (
+                ScaleMode::Fit,
+                (1280, 720),
+                [640, 360, 0, 0, 0, 60, 640, 360],
+            ),
+            // Tall source: bars left and right
+            (
+                ScaleMode::Fit,
+                (480, 640),
+                [360, 480, 0, 0, 140, 0, 360, 480],
+            ),
+            // Same aspect ratio: no bars
+            (ScaleMode::Fit, (320, 240), [640, 480, 0, 0, 0, 0, 640, 480]),
+            // Fill crops the sides of a wide source, the top and bottom of a tall one
+            (
+                ScaleMode::Fill,
+                (1280, 720),
+                [853, 480, 106, 0, 0, 0, 640, 480],
+            ),
+            (
+                ScaleMode::Fill,
+                (480, 640),
+                [640, 853, 0, 186, 0, 0, 640, 480],
+            ),
+            // Stretch ignores the aspect ratio
+            (
+                ScaleMode::Stretch,
+                (1280, 720),
+                [640, 480, 0, 0, 0, 0, 640, 480],
+            ),
+            (
+                ScaleMode::Stretch,
+                (480, 640),
+                [640, 480, 0, 0, 0, 0, 640, 480],
+            ),
+            // A degenerate source is taken as 1x1 rather than dividing by zero
+            (ScaleMode::Fit, (0, 0), [480, 480, 0, 0, 80, 0, 480, 480]),
+        ];
+        for (mode, (src_w, src_h), [sw, sh, sx, sy, dx, dy, cw, ch]) in cases {
+            let expected = Placement {
+                scaled_w: sw,
+                scaled_h: sh,
+                src_x: sx,
+                src_y: sy,
+                dst_x: dx,
+                dst_y: dy,
+                copy_w: cw,
+                copy_h: ch,
+            };
+            assert_eq!(
+                Placement::new(mode, src_w, src_h, 640, 480),
+                expected,
+                "{mode:?} {src_w}x{src_h}"
+            );
+        }
+    }
+}
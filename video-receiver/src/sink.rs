@@ -0,0 +1,393 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use ffmpeg_next as ffmpeg;
+use ffmpeg_next::{Rational, codec::Id as CodecId, format, frame, util::format::pixel::Pixel};
+
+use crate::error::{ReceiverError, is_benign};
+
+/// Output parameters negotiated by `VideoVirtualCamera` before the first frame.
+#[derive(Debug, Clone, Copy)]
+pub struct SinkConfig {
+    pub width: u32,
+    pub height: u32,
+    pub pixel_format: Pixel,
+    /// Codec used by sinks that encode (ignored by the others)
+    pub codec: CodecId,
+    /// Fixed quantizer for lossy codecs, like FFmpeg's `-q:v`; `None` for the encoder default
+    pub quality: Option<u8>,
+    /// Time base of the pts carried by the frames handed to `write_frame`
+    pub time_base: Rational,
+    pub frame_rate: Option<Rational>,
+}
+
+/// Destination for the frames produced by `VideoVirtualCamera`.
+///
+/// Frames reach `write_frame` already scaled to the configured size and
+/// converted to the configured pixel format. Encoder failures are reported as
+/// [`ReceiverError::Encode`], the output rejecting data as
+/// [`ReceiverError::Sink`].
+pub trait FrameSink: Send {
+    /// Called once, before the first frame is written.
+    fn open(&mut self, config: &SinkConfig) -> Result<(), ReceiverError>;
+
+    fn write_frame(&mut self, frame: &frame::Video) -> Result<(), ReceiverError>;
+
+    /// Flushes anything still buffered; no frames are written afterwards.
+    fn finish(&mut self) -> Result<(), ReceiverError>;
+}
+
+/// Encoder + muxer pair shared by the sinks that write through libavformat.
+struct EncodedOutput {
+    output_context: format::context::Output,
+    encoder: ffmpeg::codec::encoder::video::Encoder,
+    out_stream_index: usize,
+    enc_time_base: Rational,
+}
+
+impl EncodedOutput {
+    fn open(
+        path: &str,
+        format_name: Option<&str>,
+        config: &SinkConfig,
+    ) -> Result<Self, ReceiverError> {
+        let mut output = match format_name {
+            Some(name) => format::output_as(path, name),
+            None => format::output(path),
+        }
+        .map_err(ReceiverError::Sink)?;
+
+        let codec = ffmpeg::encoder::find(config.codec)
+            .ok_or(ReceiverError::Encode(ffmpeg::Error::EncoderNotFound))?;
+
+        // Build encoder context
+        let mut enc_ctx = ffmpeg::codec::context::Context::new_with_codec(codec)
+            .encoder()
+            .video()
+            .map_err(ReceiverError::Encode)?;
+
+        // Set negotiated size/pix_fmt (v4l2loopback will accept many, but MJPEG+YUV420P is a safe default)
+        enc_ctx.set_width(config.width.max(1));
+        enc_ctx.set_height(config.height.max(1));
+        enc_ctx.set_format(config.pixel_format);
+
+        // Pinning qmin == qmax makes rate control use exactly this quantizer
+        if let Some(quality) = config.quality {
+            enc_ctx.set_qmin(i32::from(quality));
+            enc_ctx.set_qmax(i32::from(quality));
+        }
+
+        // FPS -> frame_rate + time_base
+        if let Some(avg_fps) = config.frame_rate {
+            enc_ctx.set_frame_rate(Some(avg_fps));
+        }
+        enc_ctx.set_time_base(config.time_base);
+
+        // Containers such as mp4/mkv want codec extradata in the header rather than in-band
+        if output
+            .format()
+            .flags()
+            .contains(format::flag::Flags::GLOBAL_HEADER)
+        {
+            enc_ctx.set_flags(ffmpeg::codec::Flags::GLOBAL_HEADER);
+        }
+
+        // For v4l2 output, we still add a stream so avformat can negotiate correctly
+        let encoder = enc_ctx.open_as(codec).map_err(ReceiverError::Encode)?;
+
+        let out_stream_index;
+        {
+            let mut stream = output.add_stream(codec).map_err(ReceiverError::Sink)?;
+            stream.set_parameters(&encoder);
+            out_stream_index = stream.index();
+        }
+
+        // v4l2 output has no global header/trailer needs, but writing header is fine
+        output.write_header().map_err(ReceiverError::Sink)?;
+
+        Ok(Self {
+            output_context: output,
+            encoder,
+            out_stream_index,
+            enc_time_base: config.time_base,
+        })
+    }
+
+    fn write_frame(&mut self, frame: &frame::Video) -> Result<(), ReceiverError> {
+        // Send to encoder
+        self.encoder
+            .send_frame(frame)
+            .map_err(ReceiverError::Encode)?;
+        self.drain_packets()
+    }
+
+    fn drain_packets(&mut self) -> Result<(), ReceiverError> {
+        let out_tb = self
+            .output_context
+            .stream(self.out_stream_index)
+            .ok_or(ReceiverError::Sink(ffmpeg::Error::StreamNotFound))?
+            .time_base();
+
+        let mut packet = ffmpeg::Packet::empty();
+        loop {
+            match self.encoder.receive_packet(&mut packet) {
+                Ok(()) => {}
+                Err(err) if is_benign(&err) => return Ok(()),
+                Err(err) => return Err(ReceiverError::Encode(err)),
+            }
+            // For devices, timestamps are not always pivotal, but keep them consistent
+            packet.rescale_ts(self.enc_time_base, out_tb);
+            packet.set_stream(self.out_stream_index);
+            packet
+                .write_interleaved(&mut self.output_context)
+                .map_err(ReceiverError::Sink)?;
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), ReceiverError> {
+        self.encoder.send_eof().map_err(ReceiverError::Encode)?;
+        self.drain_packets()?;
+
+        // v4l2 doesn’t need a trailer, but calling it is harmless
+        self.output_context
+            .write_trailer()
+            .map_err(ReceiverError::Sink)
+    }
+}
+
+/// Writes frames to a v4l2loopback device such as `/dev/video2`.
+pub struct V4l2Sink {
+    device_path: String,
+    output: Option<EncodedOutput>,
+}
+
+impl V4l2Sink {
+    pub fn new(device_path: impl Into<String>) -> Self {
+        Self {
+            device_path: device_path.into(),
+            output: None,
+        }
+    }
+}
+
+impl FrameSink for V4l2Sink {
+    fn open(&mut self, config: &SinkConfig) -> Result<(), ReceiverError> {
+        // Open v4l2 output: explicit muxer name is "video4linux2" (short name "v4l2" also works)
+        self.output = Some(EncodedOutput::open(
+            &self.device_path,
+            Some("video4linux2"),
+            config,
+        )?);
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &frame::Video) -> Result<(), ReceiverError> {
+        match &mut self.output {
+            Some(output) => output.write_frame(frame),
+            None => Err(ReceiverError::Config(
+                "v4l2 sink written before open()".to_string(),
+            )),
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), ReceiverError> {
+        match self.output.take() {
+            Some(mut output) => output.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Encodes frames into a regular file; the container is picked from the extension.
+pub struct FileSink {
+    path: PathBuf,
+    output: Option<EncodedOutput>,
+}
+
+impl FileSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            output: None,
+        }
+    }
+}
+
+impl FrameSink for FileSink {
+    fn open(&mut self, config: &SinkConfig) -> Result<(), ReceiverError> {
+        let path = self.path.to_string_lossy();
+        self.output = Some(EncodedOutput::open(&path, None, config)?);
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &frame::Video) -> Result<(), ReceiverError> {
+        match &mut self.output {
+            Some(output) => output.write_frame(frame),
+            None => Err(ReceiverError::Config(
+                "File sink written before open()".to_string(),
+            )),
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), ReceiverError> {
+        match self.output.take() {
+            Some(mut output) => output.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Discards every frame. Handy to exercise the decode pipeline without v4l2loopback.
+#[derive(Default)]
+pub struct NullSink {
+    frames_written: u64,
+}
+
+impl NullSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn frames_written(&self) -> u64 {
+        self.frames_written
+    }
+}
+
+impl FrameSink for NullSink {
+    fn open(&mut self, _config: &SinkConfig) -> Result<(), ReceiverError> {
+        Ok(())
+    }
+
+    fn write_frame(&mut self, _frame: &frame::Video) -> Result<(), ReceiverError> {
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), ReceiverError> {
+        Ok(())
+    }
+}
+
+/// Frames collected by a [`MemorySink`], oldest first.
+pub type SharedFrames = Arc<Mutex<VecDeque<frame::Video>>>;
+
+/// Keeps the most recent frames in memory so other tools can embed the pipeline.
+pub struct MemorySink {
+    frames: SharedFrames,
+    max_frames: usize,
+    config: Option<SinkConfig>,
+}
+
+impl MemorySink {
+    /// `max_frames` bounds memory use: older frames are dropped once it is reached.
+    pub fn new(max_frames: usize) -> Self {
+        Self {
+            frames: Arc::new(Mutex::new(VecDeque::with_capacity(max_frames))),
+            max_frames: max_frames.max(1),
+            config: None,
+        }
+    }
+
+    /// Handle to the collected frames, still valid after the sink is moved into the camera.
+    pub fn frames(&self) -> SharedFrames {
+        Arc::clone(&self.frames)
+    }
+
+    pub fn config(&self) -> Option<&SinkConfig> {
+        self.config.as_ref()
+    }
+}
+
+impl FrameSink for MemorySink {
+    fn open(&mut self, config: &SinkConfig) -> Result<(), ReceiverError> {
+        self.config = Some(*config);
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &frame::Video) -> Result<(), ReceiverError> {
+        // A reader that panicked can't have left the queue half-updated
+        let mut frames = self.frames.lock().unwrap_or_else(PoisonError::into_inner);
+        if frames.len() == self.max_frames {
+            frames.pop_front();
+        }
+        frames.push_back(frame.clone());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), ReceiverError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn config() -> SinkConfig {
+        SinkConfig {
+            width: 64,
+            height: 48,
+            pixel_format: Pixel::YUYV422,
+            codec: CodecId::RAWVIDEO,
+            quality: None,
+            time_base: Rational::new(1, 30),
+            frame_rate: Some(Rational::new(30, 1)),
+        }
+    }
+
+    /// A frame as `VideoVirtualCamera` hands it over: scaled, converted, pts set.
+    fn output_frame(pts: i64) -> frame::Video {
+        let mut frame = frame::Video::new(Pixel::YUYV422, 64, 48);
+        frame.data_mut(0).fill(128);
+        frame.set_pts(Some(pts));
+        frame
+    }
+
+    #[test]
+    fn file_sink_writes_the_frames_out_on_finish() {
+        let dir = std::env::temp_dir().join(format!("receiver-file-sink-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.avi");
+
+        let mut sink = FileSink::new(&path);
+        sink.open(&config()).unwrap();
+        for pts in 0..3 {
+            sink.write_frame(&output_frame(pts)).unwrap();
+        }
+        let before_finish = fs::metadata(&path).unwrap().len();
+        sink.finish().unwrap();
+        let written = fs::metadata(&path).unwrap().len();
+
+        // At least the raw pictures, plus whatever was still buffered and the index
+        assert!(written >= 3 * 64 * 48 * 2, "only {written} bytes written");
+        assert!(written > before_finish);
+        // Finishing again has nothing left to flush
+        sink.finish().unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_sink_refuses_frames_before_open() {
+        let mut sink = FileSink::new(std::env::temp_dir().join("never-opened.avi"));
+        assert!(matches!(
+            sink.write_frame(&output_frame(0)),
+            Err(ReceiverError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn null_sink_takes_every_frame() {
+        let mut sink = NullSink::new();
+        sink.open(&config()).unwrap();
+        for pts in 0..5 {
+            sink.write_frame(&output_frame(pts)).unwrap();
+        }
+        sink.finish().unwrap();
+        assert_eq!(sink.frames_written(), 5);
+    }
+}
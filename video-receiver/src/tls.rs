@@ -0,0 +1,89 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use camstream_protocol::{Fingerprint, SERVER_NAME};
+use rustls::ServerConfig;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+
+const CERT_FILE: &str = "cert.der";
+const KEY_FILE: &str = "key.der";
+
+/// The receiver's TLS certificate. It is self-signed: phones trust it by its
+/// fingerprint, so it must stay the same across restarts.
+pub struct TlsIdentity {
+    config: Arc<ServerConfig>,
+    fingerprint: Fingerprint,
+}
+
+impl TlsIdentity {
+    /// Loads the certificate and key kept in `dir`, generating them there on
+    /// first run.
+    pub fn load_or_create(dir: &Path) -> Result<Self> {
+        let cert_path = dir.join(CERT_FILE);
+        let key_path = dir.join(KEY_FILE);
+        if !cert_path.exists() {
+            generate(dir, &cert_path, &key_path)?;
+            println!("🔑 Generated a TLS certificate in {}", dir.display());
+        }
+
+        let cert = fs::read(&cert_path)
+            .with_context(|| format!("Couldn't read {}", cert_path.display()))?;
+        let key =
+            fs::read(&key_path).with_context(|| format!("Couldn't read {}", key_path.display()))?;
+        let fingerprint = Fingerprint::of(&cert);
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![CertificateDer::from(cert)],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key)),
+            )
+            .with_context(|| format!("Invalid TLS certificate or key in {}", dir.display()))?;
+
+        Ok(Self {
+            config: Arc::new(config),
+            fingerprint,
+        })
+    }
+
+    pub fn config(&self) -> &Arc<ServerConfig> {
+        &self.config
+    }
+
+    /// What to enter on the phone to pin this receiver.
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.fingerprint
+    }
+
+    /// `$XDG_CONFIG_HOME/video-receiver`, falling back to `~/.config/video-receiver`.
+    pub fn default_dir() -> PathBuf {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .unwrap_or_default()
+            .join("video-receiver")
+    }
+}
+
+fn generate(dir: &Path, cert_path: &Path, key_path: &Path) -> Result<()> {
+    let rcgen::CertifiedKey { cert, key_pair } =
+        rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])
+            .context("Couldn't generate a TLS certificate")?;
+    fs::create_dir_all(dir).with_context(|| format!("Couldn't create {}", dir.display()))?;
+
+    // The key first: a certificate without its key would be kept on the next run
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(key_path)
+        .and_then(|mut file| file.write_all(&key_pair.serialize_der()))
+        .with_context(|| format!("Couldn't write {}", key_path.display()))?;
+    fs::write(cert_path, cert.der())
+        .with_context(|| format!("Couldn't write {}", cert_path.display()))
+}
@@ -0,0 +1,466 @@
+use std::fmt;
+use std::str::FromStr;
+
+use ffmpeg_next::{
+    Error, frame,
+    software::scaling::{context::Context as Scaler, flag::Flags as ScaleFlags},
+    util::format::pixel::Pixel,
+};
+
+use crate::scale::SendScaler;
+
+/// Frames in other formats are converted to this before rotating: planar, no subsampling.
+const FALLBACK_PIX_FMT: Pixel = Pixel::YUV444P;
+
+/// Clockwise rotation in quarter turns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    R0,
+    R90,
+    R180,
+    R270,
+}
+
+impl Rotation {
+    /// Rounds any angle (clockwise, degrees) to the nearest quarter turn.
+    pub fn from_degrees(degrees: f64) -> Self {
+        match ((degrees / 90.0).round() as i64).rem_euclid(4) {
+            1 => Self::R90,
+            2 => Self::R180,
+            3 => Self::R270,
+            _ => Self::R0,
+        }
+    }
+
+    pub fn degrees(self) -> u16 {
+        self.quarter_turns() * 90
+    }
+
+    fn quarter_turns(self) -> u16 {
+        match self {
+            Self::R0 => 0,
+            Self::R90 => 1,
+            Self::R180 => 2,
+            Self::R270 => 3,
+        }
+    }
+
+    fn from_quarter_turns(turns: i32) -> Self {
+        Self::from_degrees(f64::from(turns * 90))
+    }
+
+    /// Swaps width and height.
+    fn is_transposing(self) -> bool {
+        matches!(self, Self::R90 | Self::R270)
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(Self::R0),
+            "90" => Ok(Self::R90),
+            "180" => Ok(Self::R180),
+            "270" | "-90" => Ok(Self::R270),
+            _ => Err(format!(
+                "invalid rotation '{s}', expected 0, 90, 180 or 270"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.degrees())
+    }
+}
+
+/// Orientation fix applied to incoming frames: flips first (in the sender's
+/// orientation), then a clockwise rotation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Transform {
+    pub rotation: Rotation,
+    pub hflip: bool,
+    pub vflip: bool,
+}
+
+impl Transform {
+    pub fn is_identity(self) -> bool {
+        self.canonical() == (Rotation::R0, false)
+    }
+
+    /// Reads the rotation stored in an FFmpeg display matrix (nine native-endian
+    /// 16.16 fixed-point `i32`, as found in frame/stream side data).
+    ///
+    /// The result turns the picture upright. Mirrored matrices are treated as a
+    /// horizontal flip before the rotation.
+    pub fn from_display_matrix(data: &[u8]) -> Option<Self> {
+        if data.len() < 9 * 4 {
+            return None;
+        }
+        let m: Vec<f64> = data
+            .chunks_exact(4)
+            .take(9)
+            .map(|c| f64::from(i32::from_ne_bytes([c[0], c[1], c[2], c[3]])) / 65536.0)
+            .collect();
+
+        let mirrored = m[0] * m[4] - m[1] * m[3] < 0.0;
+        let (a, c) = if mirrored {
+            (-m[0], -m[3])
+        } else {
+            (m[0], m[3])
+        };
+        let (b, d) = (m[1], m[4]);
+        if a.hypot(c) == 0.0 || b.hypot(d) == 0.0 {
+            return None;
+        }
+
+        // Same as -av_display_rotation_get(), i.e. the clockwise turn that fixes the picture
+        let degrees = (b / b.hypot(d)).atan2(a / a.hypot(c)).to_degrees();
+        Some(Self {
+            rotation: Rotation::from_degrees(degrees),
+            hflip: mirrored,
+            vflip: false,
+        })
+    }
+
+    /// `self` followed by `next`.
+    pub fn then(self, next: Self) -> Self {
+        let (r1, f1) = self.canonical();
+        let (r2, f2) = next.canonical();
+        let (r1, r2) = (i32::from(r1.quarter_turns()), i32::from(r2.quarter_turns()));
+
+        // Mirroring reverses the direction of any rotation done before it
+        let (turns, hflip) = if f2 { (r2 - r1, !f1) } else { (r2 + r1, f1) };
+        Self {
+            rotation: Rotation::from_quarter_turns(turns),
+            hflip,
+            vflip: false,
+        }
+    }
+
+    /// Same transform as a horizontal flip (or none) followed by a rotation:
+    /// a vertical flip is a horizontal flip plus half a turn.
+    fn canonical(self) -> (Rotation, bool) {
+        if self.vflip {
+            let turns = i32::from(self.rotation.quarter_turns()) + 2;
+            (Rotation::from_quarter_turns(turns), !self.hflip)
+        } else {
+            (self.rotation, self.hflip)
+        }
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rotate {}°", self.rotation)?;
+        if self.hflip {
+            write!(f, ", hflip")?;
+        }
+        if self.vflip {
+            write!(f, ", vflip")?;
+        }
+        Ok(())
+    }
+}
+
+/// Rotates/mirrors decoded frames.
+///
+/// 8-bit planar formats with the same chroma subsampling on both axes (the
+/// usual yuv420p, yuv444p...) are rotated plane by plane as they are; anything
+/// else goes through [`FALLBACK_PIX_FMT`] first.
+#[derive(Default)]
+pub struct FrameTransformer {
+    convert: Option<(SendScaler, (u32, u32, Pixel))>,
+}
+
+impl FrameTransformer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the transformed frame, or `None` when `transform` leaves it as is.
+    pub fn apply(
+        &mut self,
+        src: &frame::Video,
+        transform: Transform,
+    ) -> Result<Option<frame::Video>, Error> {
+        if transform.is_identity() {
+            return Ok(None);
+        }
+        let (rotation, hflip) = transform.canonical();
+
+        let converted;
+        let src = if can_rotate_planes(src.format()) {
+            src
+        } else {
+            converted = self.convert(src)?;
+            &converted
+        };
+
+        let (out_w, out_h) = if rotation.is_transposing() {
+            (src.height(), src.width())
+        } else {
+            (src.width(), src.height())
+        };
+        let mut out = frame::Video::new(src.format(), out_w, out_h);
+        for plane in 0..src.planes() {
+            rotate_plane(src, &mut out, plane, rotation, hflip);
+        }
+        out.set_pts(src.pts());
+        out.set_color_range(src.color_range());
+        out.set_color_space(src.color_space());
+        Ok(Some(out))
+    }
+
+    fn convert(&mut self, src: &frame::Video) -> Result<frame::Video, Error> {
+        let key = (src.width(), src.height(), src.format());
+        let scaler = match &mut self.convert {
+            Some((scaler, built)) if *built == key => scaler,
+            slot => {
+                let scaler = Scaler::get(
+                    src.format(),
+                    src.width(),
+                    src.height(),
+                    FALLBACK_PIX_FMT,
+                    src.width(),
+                    src.height(),
+                    ScaleFlags::POINT,
+                )?;
+                &mut slot.insert((SendScaler(scaler), key)).0
+            }
+        };
+
+        let mut out = frame::Video::empty();
+        scaler.run(src, &mut out)?;
+        out.set_pts(src.pts());
+        Ok(out)
+    }
+}
+
+/// One byte per sample on every plane, and planes that stay consistent when transposed.
+fn can_rotate_planes(format: Pixel) -> bool {
+    matches!(
+        format,
+        Pixel::YUV420P
+            | Pixel::YUVJ420P
+            | Pixel::YUV444P
+            | Pixel::YUVJ444P
+            | Pixel::YUV410P
+            | Pixel::GRAY8
+            | Pixel::GBRP
+    )
+}
+
+fn rotate_plane(
+    src: &frame::Video,
+    dst: &mut frame::Video,
+    plane: usize,
+    rotation: Rotation,
+    hflip: bool,
+) {
+    let (src_w, src_h) = (
+        src.plane_width(plane) as usize,
+        src.plane_height(plane) as usize,
+    );
+    let (dst_w, dst_h) = (
+        dst.plane_width(plane) as usize,
+        dst.plane_height(plane) as usize,
+    );
+    let src_stride = src.stride(plane);
+    let dst_stride = dst.stride(plane);
+    let src_data = src.data(plane);
+    let dst_data = dst.data_mut(plane);
+
+    for y in 0..dst_h {
+        let row = &mut dst_data[y * dst_stride..y * dst_stride + dst_w];
+        for (x, sample) in row.iter_mut().enumerate() {
+            // Position in the (mirrored) source that ends up at (x, y) after the clockwise turn
+            let (sx, sy) = match rotation {
+                Rotation::R0 => (x, y),
+                Rotation::R90 => (y, src_h - 1 - x),
+                Rotation::R180 => (src_w - 1 - x, src_h - 1 - y),
+                Rotation::R270 => (src_w - 1 - y, x),
+            };
+            let sx = if hflip { src_w - 1 - sx } else { sx };
+            *sample = src_data[sy * src_stride + sx];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROTATIONS: [Rotation; 4] = [Rotation::R0, Rotation::R90, Rotation::R180, Rotation::R270];
+
+    /// Every combination of rotation and flips.
+    fn all_transforms() -> Vec<Transform> {
+        let mut all = Vec::new();
+        for rotation in ROTATIONS {
+            for (hflip, vflip) in [(false, false), (true, false), (false, true), (true, true)] {
+                all.push(Transform {
+                    rotation,
+                    hflip,
+                    vflip,
+                });
+            }
+        }
+        all
+    }
+
+    /// Reference implementation on a grid of labels: flips, then clockwise quarter turns.
+    fn apply(grid: &[Vec<u8>], transform: Transform) -> Vec<Vec<u8>> {
+        let mut grid = grid.to_vec();
+        if transform.hflip {
+            grid.iter_mut().for_each(|row| row.reverse());
+        }
+        if transform.vflip {
+            grid.reverse();
+        }
+        for _ in 0..transform.rotation.degrees() / 90 {
+            let (h, w) = (grid.len(), grid[0].len());
+            grid = (0..w)
+                .map(|r| (0..h).map(|c| grid[h - 1 - c][r]).collect())
+                .collect();
+        }
+        grid
+    }
+
+    /// What `av_display_rotation_set` then `av_display_matrix_flip` store,
+    /// `degrees` being counter-clockwise.
+    fn display_matrix(degrees: f64, hflip: bool) -> Vec<u8> {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let mut m = [cos, sin, 0.0, -sin, cos, 0.0, 0.0, 0.0, 0.0];
+        if hflip {
+            for i in [0, 3, 6] {
+                m[i] = -m[i];
+            }
+        }
+        let mut fixed: Vec<i32> = m.iter().map(|v| (v * 65536.0).round() as i32).collect();
+        fixed[8] = 1 << 30;
+        fixed.iter().flat_map(|v| v.to_ne_bytes()).collect()
+    }
+
+    #[test]
+    fn parses_rotations() {
+        for (input, expected) in [
+            ("0", Rotation::R0),
+            ("90", Rotation::R90),
+            ("180", Rotation::R180),
+            ("270", Rotation::R270),
+            ("-90", Rotation::R270),
+        ] {
+            assert_eq!(input.parse(), Ok(expected), "{input}");
+        }
+        for input in ["", "45", "360", "-180", "ninety"] {
+            assert!(input.parse::<Rotation>().is_err(), "{input}");
+        }
+        for rotation in ROTATIONS {
+            assert_eq!(rotation.to_string().parse(), Ok(rotation));
+        }
+    }
+
+    #[test]
+    fn rounds_degrees_to_quarter_turns() {
+        for (degrees, expected) in [
+            (0.0, Rotation::R0),
+            (44.0, Rotation::R0),
+            (46.0, Rotation::R90),
+            (-90.0, Rotation::R270),
+            (180.0, Rotation::R180),
+            (-180.0, Rotation::R180),
+            (450.0, Rotation::R90),
+        ] {
+            assert_eq!(Rotation::from_degrees(degrees), expected, "{degrees}");
+        }
+    }
+
+    #[test]
+    fn reads_display_matrices() {
+        for (degrees, rotation) in [
+            (0.0, Rotation::R0),
+            (90.0, Rotation::R90),
+            (180.0, Rotation::R180),
+            (270.0, Rotation::R270),
+            (-90.0, Rotation::R270),
+        ] {
+            for hflip in [false, true] {
+                assert_eq!(
+                    Transform::from_display_matrix(&display_matrix(degrees, hflip)),
+                    Some(Transform {
+                        rotation,
+                        hflip,
+                        vflip: false,
+                    }),
+                    "{degrees}° hflip={hflip}"
+                );
+            }
+        }
+
+        assert_eq!(
+            Transform::from_display_matrix(&display_matrix(90.0, false)[..32]),
+            None
+        );
+        assert_eq!(Transform::from_display_matrix(&[0; 36]), None);
+    }
+
+    #[test]
+    fn then_matches_applying_one_after_the_other() {
+        // Not square, so a missed transposition shows
+        let grid = vec![vec![1, 2, 3], vec![4, 5, 6]];
+        for first in all_transforms() {
+            for second in all_transforms() {
+                assert_eq!(
+                    apply(&grid, first.then(second)),
+                    apply(&apply(&grid, first), second),
+                    "{first} then {second}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn flips_reverse_the_rotation_direction() {
+        let hflip = Transform {
+            hflip: true,
+            ..Transform::default()
+        };
+        let r90 = Transform {
+            rotation: Rotation::R90,
+            ..Transform::default()
+        };
+        assert_eq!(
+            hflip.then(r90),
+            Transform {
+                rotation: Rotation::R90,
+                hflip: true,
+                vflip: false,
+            }
+        );
+        assert_eq!(
+            r90.then(hflip),
+            Transform {
+                rotation: Rotation::R270,
+                hflip: true,
+                vflip: false,
+            }
+        );
+        assert!(r90.then(r90).then(r90).then(r90).is_identity());
+        assert!(hflip.then(r90).then(hflip).then(r90).is_identity());
+    }
+
+    #[test]
+    fn then_is_associative() {
+        for a in all_transforms() {
+            for b in all_transforms() {
+                for c in all_transforms() {
+                    assert_eq!(a.then(b).then(c), a.then(b.then(c)), "{a}, {b}, {c}");
+                }
+            }
+        }
+    }
+}